@@ -10,4 +10,4 @@ pub enum Error {
     WMIError(#[from] wmi::WMIError),
 }
 
-pub use listen::{Process, listen_process_creation};
+pub use listen::{Process, listen_process_creation, listen_process_exit};
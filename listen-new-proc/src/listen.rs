@@ -3,6 +3,7 @@ use std::time::Duration;
 
 use futures_lite::StreamExt;
 use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use wmi::{FilterValue, WMIConnection};
 
 use crate::Error;
@@ -14,6 +15,13 @@ struct NewProcessEvent {
     target_instance: Process,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename = "__InstanceDeletionEvent")]
+#[serde(rename_all = "PascalCase")]
+struct ExitedProcessEvent {
+    target_instance: Process,
+}
+
 #[derive(Deserialize, Debug, Default)]
 #[serde(rename = "Win32_Process")]
 #[serde(rename_all = "PascalCase")]
@@ -24,17 +32,41 @@ pub struct Process {
     pub name: String,
 }
 
-pub async fn listen_process_creation(mut callback: impl FnMut(Process)) -> Result<(), Error> {
+trait ProcessEvent: DeserializeOwned {
+    fn into_process(self) -> Process;
+}
+
+impl ProcessEvent for NewProcessEvent {
+    fn into_process(self) -> Process {
+        self.target_instance
+    }
+}
+
+impl ProcessEvent for ExitedProcessEvent {
+    fn into_process(self) -> Process {
+        self.target_instance
+    }
+}
+
+pub async fn listen_process_creation(callback: impl FnMut(Process)) -> Result<(), Error> {
+    listen::<NewProcessEvent>(callback).await
+}
+
+pub async fn listen_process_exit(callback: impl FnMut(Process)) -> Result<(), Error> {
+    listen::<ExitedProcessEvent>(callback).await
+}
+
+async fn listen<E: ProcessEvent>(mut callback: impl FnMut(Process)) -> Result<(), Error> {
     let wmi_con = WMIConnection::new()?;
 
     let mut filters = HashMap::<String, FilterValue>::new();
     filters.insert("TargetInstance".to_owned(), FilterValue::is_a::<Process>()?);
 
-    let mut stream = wmi_con
-        .async_filtered_notification::<NewProcessEvent>(&filters, Some(Duration::from_secs(1)))?;
+    let mut stream =
+        wmi_con.async_filtered_notification::<E>(&filters, Some(Duration::from_secs(1)))?;
 
     while let Some(result) = stream.next().await {
-        let process = result?.target_instance;
+        let process = result?.into_process();
         callback(process);
     }
 
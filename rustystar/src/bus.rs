@@ -0,0 +1,175 @@
+use std::sync::Mutex;
+
+use kanal::{AsyncReceiver, Sender};
+use serde::Serialize;
+
+use crate::decision::{Action, unix_millis};
+
+/// events a slow subscriber may lag behind, later ones are dropped for it
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Stamped>>> = Mutex::new(Vec::new());
+
+/// something RustyStar noticed or did
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ForegroundChanged {
+        pid: u32,
+    },
+    ProcessCreated {
+        pid: u32,
+        name: String,
+    },
+    Throttled {
+        pid: u32,
+        name: String,
+    },
+    Boosted {
+        pid: u32,
+        name: String,
+    },
+    Restored {
+        pid: u32,
+        name: String,
+    },
+    Failed {
+        pid: u32,
+        name: String,
+        action: Action,
+        error: ErrorClass,
+        message: String,
+    },
+    Paused {
+        /// `None` if paused until resumed
+        seconds: Option<u64>,
+    },
+    Resumed,
+    ConfigReloaded,
+}
+
+/// why applying an action failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// e.g. protected or elevated processes
+    AccessDenied,
+    /// the process exited meanwhile
+    NotFound,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stamped {
+    /// milliseconds since unix epoch
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl Event {
+    /// outcome of applying `action` to a process
+    pub fn applied(pid: u32, name: String, action: Action) -> Self {
+        match action {
+            Action::Throttle => Event::Throttled { pid, name },
+            Action::Boost => Event::Boosted { pid, name },
+            Action::Restore => Event::Restored { pid, name },
+        }
+    }
+}
+
+impl ErrorClass {
+    /// class of a failed win32 call by its `HRESULT`
+    pub fn of(hresult: i32) -> Self {
+        // HRESULT_FROM_WIN32 of ERROR_ACCESS_DENIED, ERROR_INVALID_PARAMETER
+        const ACCESS_DENIED: u32 = 0x8007_0005;
+        const INVALID_PARAMETER: u32 = 0x8007_0057;
+
+        match hresult as u32 {
+            ACCESS_DENIED => ErrorClass::AccessDenied,
+            // `OpenProcess` of a pid which no longer exists
+            INVALID_PARAMETER => ErrorClass::NotFound,
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+/// send `event` to all subscribers
+pub fn publish(event: Event) {
+    let Ok(mut subscribers) = SUBSCRIBERS.lock() else {
+        return;
+    };
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let stamped = Stamped {
+        time: unix_millis(),
+        event,
+    };
+    // a full queue only loses this event, a closed one is gone for good
+    subscribers.retain(|subscriber| subscriber.try_send(stamped.clone()).is_ok());
+}
+
+/// receive events published from now on
+pub fn subscribe() -> AsyncReceiver<Stamped> {
+    let (tx, rx) = kanal::bounded(SUBSCRIBER_CAPACITY);
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(tx);
+    }
+    rx.to_async()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ErrorClass, Event, publish, subscribe};
+    use crate::decision::Action;
+
+    #[test]
+    fn subscribers_receive_published_events() {
+        let events = subscribe();
+        publish(Event::Resumed);
+        publish(Event::applied(42, "game.exe".to_string(), Action::Boost));
+
+        let received = events.to_sync();
+        let first = received.try_recv().unwrap().unwrap();
+        assert_eq!(first.event, Event::Resumed);
+
+        let second = received.try_recv().unwrap().unwrap();
+        let json = serde_json::to_value(&second).unwrap();
+        assert_eq!(json["event"], "boosted");
+        assert_eq!(json["pid"], 42);
+        assert!(json["time"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn failures_are_classified() {
+        assert_eq!(
+            ErrorClass::of(0x8007_0005_u32 as i32),
+            ErrorClass::AccessDenied
+        );
+        assert_eq!(ErrorClass::of(0x8007_0057_u32 as i32), ErrorClass::NotFound);
+        assert_eq!(ErrorClass::of(-1), ErrorClass::Other);
+
+        let failed = Event::Failed {
+            pid: 4,
+            name: "System".to_string(),
+            action: Action::Throttle,
+            error: ErrorClass::AccessDenied,
+            message: "Access is denied.".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(failed).unwrap(),
+            json!({
+                "event": "failed",
+                "pid": 4,
+                "name": "System",
+                "action": "throttle",
+                "error": "access_denied",
+                "message": "Access is denied.",
+            })
+        );
+    }
+}
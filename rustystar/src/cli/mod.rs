@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::config::layer::Sources;
+use crate::config::{PORTABLE_DIR, check_profile};
+use crate::decision::unix_millis;
+use crate::events::foreground_process_id;
+
+mod process;
+mod remote;
+mod status;
+
+pub use remote::already_running;
+
+type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// use this config file instead of the default one
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// override a config key for this run, e.g. `listen_new_process.mode=blacklist_only`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    /// print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// throttle processes in background, the default without a command
+    Run,
+    /// show whether RustyStar is running, and the files it uses
+    Status,
+    /// list processes
+    Ps,
+    /// enable EcoQoS for processes
+    Throttle {
+        /// process id or executable name, e.g. `1234` or `game.exe`
+        #[arg(required_unless_present = "foreground")]
+        target: Option<Target>,
+        /// the process owning the foreground window
+        #[arg(long, conflicts_with = "target")]
+        foreground: bool,
+    },
+    /// disable EcoQoS for processes
+    Boost {
+        /// process id or executable name, e.g. `1234` or `game.exe`
+        #[arg(required_unless_present = "foreground")]
+        target: Option<Target>,
+        /// the process owning the foreground window
+        #[arg(long, conflicts_with = "target")]
+        foreground: bool,
+    },
+    /// hand processes back to Windows
+    Restore {
+        /// process id or executable name, e.g. `1234` or `game.exe`
+        #[arg(required_unless_present = "all")]
+        target: Option<Target>,
+        /// restore every process except whitelisted ones
+        #[arg(long, conflicts_with = "target")]
+        all: bool,
+    },
+    /// stop throttling in the running instance, processes it changed are restored
+    Pause {
+        /// resume automatically after, e.g. `30s`, `10m` or `1h30m`
+        #[arg(value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// continue throttling in the running instance
+    Resume,
+    /// reload configuration of the running instance
+    Reload,
+    /// recent decisions of the running instance, newest last
+    History {
+        /// process id or executable name, e.g. `1234` or `game.exe`
+        target: Option<Target>,
+        /// only decisions made within this long, e.g. `10m`
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<Duration>,
+        /// only decisions made at least this long ago, e.g. `5m`
+        #[arg(long, value_parser = parse_duration)]
+        until: Option<Duration>,
+        /// only the newest ones
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// per-executable statistics of the running instance, as a Markdown table
+    Report {
+        /// only statistics of this long, e.g. `1d`, rounded to whole hours
+        #[arg(long, value_parser = parse_duration)]
+        since: Option<Duration>,
+    },
+    /// inspect configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// trace the checks deciding how RustyStar treats a process
+    Explain {
+        /// process id or executable name, e.g. `1234` or `game.exe`
+        target: Target,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// validate `config.toml`, report problems with their location
+    Check,
+    /// print the effective configuration after all layers are merged
+    Show {
+        /// annotate every key with where its value came from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// print paths of config files
+    Path,
+}
+
+/// a process, by its id or executable name
+#[derive(Debug, Clone)]
+pub enum Target {
+    Pid(u32),
+    Name(OsString),
+}
+
+impl FromStr for Target {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(pid) => Target::Pid(pid),
+            Err(_) => Target::Name(s.into()),
+        })
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Pid(pid) => write!(f, "process {pid}"),
+            Target::Name(name) => write!(f, "{}", name.display()),
+        }
+    }
+}
+
+impl Cli {
+    pub fn from_env() -> Self {
+        // without arguments, RustyStar runs as usual and only writes to logs
+        if std::env::args_os().len() > 1 {
+            attach_console();
+        }
+        Self::parse()
+    }
+}
+
+/// run a command other than [`Command::Run`]
+pub async fn run_command(command: Command, sources: Sources, json: bool) -> Result<()> {
+    let success = match command {
+        Command::Run => unreachable!("`run` is handled by main"),
+        Command::Status => status::status(&sources, json).await?,
+        Command::Ps => process::ps(&sources, json).await?,
+        Command::Throttle { target, foreground } => {
+            let target = target_or_foreground(target, foreground)?;
+            process::toggle(&sources, Some(&target), Some(true), json).await?
+        }
+        Command::Boost { target, foreground } => {
+            let target = target_or_foreground(target, foreground)?;
+            process::toggle(&sources, Some(&target), Some(false), json).await?
+        }
+        Command::Pause { duration } => {
+            let seconds = duration.map(|duration| duration.as_secs());
+            remote::forward("pause", json!({ "seconds": seconds }), json).await?
+        }
+        Command::Resume => remote::forward("resume", Value::Null, json).await?,
+        Command::Reload => remote::forward("reload", Value::Null, json).await?,
+        Command::History {
+            target,
+            since,
+            until,
+            limit,
+        } => {
+            let ago =
+                |duration: Duration| unix_millis().saturating_sub(duration.as_millis() as u64);
+            let (pid, name) = match target {
+                Some(Target::Pid(pid)) => (Some(pid), None),
+                Some(Target::Name(name)) => (None, Some(name.to_string_lossy().into_owned())),
+                None => (None, None),
+            };
+            let params = json!({
+                "pid": pid,
+                "name": name,
+                "since": since.map(ago),
+                "until": until.map(ago),
+                "limit": limit,
+            });
+            remote::forward("decisions", params, json).await?
+        }
+        Command::Report { since } => {
+            let since = since.map(|since| unix_millis().saturating_sub(since.as_millis() as u64));
+            remote::forward("report", json!({ "since": since }), json).await?
+        }
+        Command::Restore { target, all } => {
+            // `--all` conflicts with a target, no target restores every process
+            let target = target.filter(|_| !all);
+            process::toggle(&sources, target.as_ref(), None, json).await?
+        }
+        Command::Explain { target } => process::explain(&sources, &target, json).await?,
+        Command::Config(ConfigCommand::Check) => check_profile(&sources, json).await?,
+        Command::Config(ConfigCommand::Show { origin }) => {
+            let layered = sources.load().await?;
+            if json {
+                let origins = origin.then(|| {
+                    layered
+                        .origins
+                        .iter()
+                        .map(|(key, origin)| (key.clone(), origin.to_string()))
+                        .collect::<BTreeMap<_, _>>()
+                });
+                print_json(&serde_json::json!({ "config": layered.config, "origins": origins }));
+            } else {
+                print!("{}", layered.render(origin)?);
+            }
+            true
+        }
+        Command::Config(ConfigCommand::Path) => {
+            let layered = sources.load().await.ok();
+            let includes = layered.map(|layered| layered.includes).unwrap_or_default();
+            if json {
+                print_json(&serde_json::json!({
+                    "user": sources.user,
+                    "policy": sources.policy,
+                    "includes": includes,
+                    "portable": PORTABLE_DIR.is_some(),
+                }));
+            } else {
+                println!("user config:    {}", sources.user.display());
+                println!("machine policy: {}", sources.policy.display());
+                for include in includes {
+                    println!("included:       {}", include.display());
+                }
+            }
+            true
+        }
+    };
+
+    if !success {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `target`, or the foreground process with `--foreground`
+fn target_or_foreground(target: Option<Target>, foreground: bool) -> Result<Target> {
+    match target {
+        Some(target) if !foreground => Ok(target),
+        _ => foreground_process_id()
+            .map(Target::Pid)
+            .ok_or_else(|| "no window is in the foreground".into()),
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("failed to serialize output: {e}"),
+    }
+}
+
+fn parse_override(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, found `{arg}`"))?;
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
+
+/// parse durations like `30s`, `10m` or `1h30m`
+pub fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let invalid = || format!("expected a duration like `30s`, `10m` or `1h30m`, found `{arg}`");
+
+    let mut seconds = 0_u64;
+    let mut number = String::new();
+    for c in arg.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value = number.parse::<u64>().map_err(|_| invalid())?;
+        seconds = seconds.saturating_add(value.saturating_mul(unit));
+        number.clear();
+    }
+
+    if !number.is_empty() || seconds == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// format durations like `1h30m`, the inverse of [`parse_duration`] to the second
+pub fn format_duration(duration: Duration) -> String {
+    let mut seconds = duration.as_secs();
+    if seconds == 0 {
+        return "0s".to_string();
+    }
+
+    let mut formatted = String::new();
+    for (unit, length) in [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)] {
+        if seconds >= length {
+            formatted.push_str(&format!("{}{unit}", seconds / length));
+            seconds %= length;
+        }
+    }
+    formatted
+}
+
+/// with `hide-to-tray`, RustyStar has no console, borrow the one of its caller
+fn attach_console() {
+    #[cfg(feature = "hide-to-tray")]
+    unsafe {
+        use windows::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+
+        _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{format_duration, parse_duration};
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_millis(5_400_900)), "1h30m");
+        assert_eq!(format_duration(Duration::from_secs(90061)), "1d1h1m1s");
+        for text in ["30s", "10m", "1h30m", "2d5s"] {
+            assert_eq!(format_duration(parse_duration(text).unwrap()), text);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_without_unit() {
+        for arg in ["", "10", "m", "10x", "0s", "1h30"] {
+            assert!(parse_duration(arg).is_err(), "{arg:?} was accepted");
+        }
+    }
+}
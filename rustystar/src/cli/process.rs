@@ -0,0 +1,393 @@
+use std::ffi::OsString;
+
+use ahash::AHashMap;
+use serde::Serialize;
+use serde_json::{Value, json};
+use win32_ecoqos::process::{ThrottlingState, throttling_state_handle, toggle_efficiency_mode};
+use win32_ecoqos::utils::{Process, Processes};
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Threading::{
+    ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, GetPriorityClass,
+    HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, OpenProcess,
+    PROCESS_CREATION_FLAGS, PROCESS_QUERY_INFORMATION, REALTIME_PRIORITY_CLASS,
+};
+
+use crate::WHITELIST;
+use crate::bypass::whitelisted;
+use crate::cli::remote::forward;
+use crate::cli::{Result, Target, print_json};
+use crate::config::DEFAULT_WHITELIST;
+use crate::config::layer::{Layered, Sources};
+use crate::decision::{Action, Facts, Step, Trace, Trigger, decide};
+use crate::events::{foreground_process_id, fullscreen_running};
+use crate::ipc::Client;
+use crate::utils::{ProcTree, instance_running};
+
+#[derive(Serialize)]
+struct ProcessEntry {
+    pid: u32,
+    parent_pid: u32,
+    name: String,
+    /// `None` if the process can't be queried, e.g. protected ones
+    ecoqos: Option<bool>,
+    /// EcoQoS state was set explicitly, by RustyStar, the process itself or another tool
+    explicit: Option<bool>,
+    /// action the running instance of RustyStar applied to the process, `None` if none
+    changed: Option<String>,
+    priority: Option<&'static str>,
+    /// whitelist entry or blacklist which matched the process
+    matched: Option<String>,
+    in_foreground_tree: bool,
+}
+
+#[derive(Serialize)]
+struct Outcome {
+    pid: u32,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Explanation {
+    pid: u32,
+    name: String,
+    /// which whitelist entry matched
+    whitelisted_by: Option<String>,
+    foreground_pid: Option<u32>,
+    /// traces of events RustyStar listens to, empty if it doesn't touch processes at all
+    traces: Vec<Trace>,
+}
+
+/// load configuration, and the whitelist the background service would use
+async fn load_whitelist(sources: &Sources) -> Result<Layered> {
+    let layered = sources.load().await?;
+    if let Ok(mut whitelist) = WHITELIST.write() {
+        *whitelist = layered
+            .config
+            .whitelist()
+            .iter()
+            .map(OsString::from)
+            .collect();
+    }
+    Ok(layered)
+}
+
+pub async fn ps(sources: &Sources, json: bool) -> Result<bool> {
+    let layered = load_whitelist(sources).await?;
+
+    let procs = Processes::try_new()?.collect::<Vec<_>>();
+    let tree = ProcTree::from(procs.iter());
+    let foreground_pid = foreground_process_id();
+    let changed = changed_by_instance().await;
+
+    let entries = procs
+        .iter()
+        .map(|process| {
+            let name = process.process_name.to_string_lossy().into_owned();
+            let (state, priority) = query_process(process.process_id);
+            ProcessEntry {
+                pid: process.process_id,
+                parent_pid: process.process_parent_id,
+                ecoqos: state.map(|state| state.enabled),
+                explicit: state.map(|state| state.explicit),
+                // the pid may have been reused since
+                changed: changed
+                    .get(&process.process_id)
+                    .filter(|(touched, _)| *touched == name)
+                    .map(|(_, action)| action.clone()),
+                priority,
+                matched: whitelisted_by(&layered, &name).or_else(|| {
+                    let blacklist = &layered.config.listen_new_process.blacklist;
+                    blacklist.contains(&name).then(|| "blacklist".to_string())
+                }),
+                in_foreground_tree: foreground_pid
+                    .is_some_and(|foreground| tree.is_in_tree(foreground, process.process_id)),
+                name,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if json {
+        print_json(&entries);
+        return Ok(true);
+    }
+
+    println!(
+        "{:>7} {:>7}  {:<9} {:<9} {:<12} {:<2} {:<18} NAME",
+        "PID", "PARENT", "ECOQOS", "CHANGED", "PRIORITY", "FG", "MATCHED"
+    );
+    for entry in entries {
+        let ecoqos = match (entry.ecoqos, entry.explicit) {
+            (Some(enabled), Some(explicit)) => format!(
+                "{}{}",
+                if enabled { "on" } else { "off" },
+                if explicit { " (set)" } else { "" }
+            ),
+            _ => "?".to_string(),
+        };
+        println!(
+            "{:>7} {:>7}  {ecoqos:<9} {:<9} {:<12} {:<2} {:<18} {}",
+            entry.pid,
+            entry.parent_pid,
+            entry.changed.as_deref().unwrap_or(""),
+            entry.priority.unwrap_or("?"),
+            if entry.in_foreground_tree { "*" } else { "" },
+            entry.matched.as_deref().unwrap_or(""),
+            entry.name
+        );
+    }
+    Ok(true)
+}
+
+/// name and action of processes the running instance changed, by pid
+///
+/// Empty if RustyStar isn't running, it hands every process back on exit.
+async fn changed_by_instance() -> AHashMap<u32, (String, String)> {
+    if !instance_running() {
+        return AHashMap::new();
+    }
+    let Ok(mut client) = Client::connect().await else {
+        return AHashMap::new();
+    };
+    let Ok(Ok(Value::Array(touched))) = client.call("touched", json!({})).await else {
+        return AHashMap::new();
+    };
+
+    touched
+        .iter()
+        .filter_map(|touched| {
+            let pid = u32::try_from(touched["pid"].as_u64()?).ok()?;
+            let name = touched["name"].as_str()?.to_string();
+            let action = touched["action"].as_str()?.to_string();
+            Some((pid, (name, action)))
+        })
+        .collect()
+}
+
+/// EcoQoS state and priority class of a process, `None` if access was denied
+fn query_process(pid: u32) -> (Option<ThrottlingState>, Option<&'static str>) {
+    let Ok(hprocess) = (unsafe { OpenProcess(PROCESS_QUERY_INFORMATION, false, pid) }) else {
+        return (None, None);
+    };
+
+    let state = unsafe { throttling_state_handle(hprocess) }.ok();
+    let priority = match PROCESS_CREATION_FLAGS(unsafe { GetPriorityClass(hprocess) }) {
+        IDLE_PRIORITY_CLASS => Some("idle"),
+        BELOW_NORMAL_PRIORITY_CLASS => Some("below_normal"),
+        NORMAL_PRIORITY_CLASS => Some("normal"),
+        ABOVE_NORMAL_PRIORITY_CLASS => Some("above_normal"),
+        HIGH_PRIORITY_CLASS => Some("high"),
+        REALTIME_PRIORITY_CLASS => Some("realtime"),
+        _ => None,
+    };
+    let _ = unsafe { CloseHandle(hprocess) };
+
+    (state, priority)
+}
+
+/// which whitelist entry matched `name`, if any
+fn whitelisted_by(layered: &Layered, name: &str) -> Option<String> {
+    if !whitelisted(name) {
+        None
+    } else if DEFAULT_WHITELIST.contains(&name) {
+        Some("built-in whitelist".to_string())
+    } else {
+        Some(match layered.origins.get("whitelist_extra") {
+            Some(origin) => format!("whitelist_extra, from {origin}"),
+            None => "whitelist_extra".to_string(),
+        })
+    }
+}
+
+/// apply `enable` to processes matching `target`, or to all but whitelisted ones if `None`
+///
+/// requests are forwarded to the running instance, if there is one
+pub async fn toggle(
+    sources: &Sources,
+    target: Option<&Target>,
+    enable: Option<bool>,
+    json: bool,
+) -> Result<bool> {
+    load_whitelist(sources).await?;
+
+    let action = match enable {
+        Some(true) => "throttle",
+        Some(false) => "boost",
+        None => "restore",
+    };
+
+    // the running instance sweeps all processes itself, including those we can't see
+    if target.is_none() && instance_running() {
+        return forward(action, json!({}), json).await;
+    }
+
+    // the running instance may be elevated, and reach processes we can't
+    let mut client = if instance_running() {
+        Client::connect().await.ok()
+    } else {
+        None
+    };
+
+    let mut outcomes = vec![];
+    for Process {
+        process_id,
+        process_name,
+        ..
+    } in Processes::try_new()?
+    {
+        let matches = match target {
+            Some(Target::Pid(pid)) => process_id == *pid,
+            Some(Target::Name(name)) => process_name.eq_ignore_ascii_case(name),
+            None => !whitelisted(&process_name),
+        };
+        if !matches {
+            continue;
+        }
+
+        let error = match &mut client {
+            Some(client) => match client.call(action, json!({ "pid": process_id })).await? {
+                Ok(_) => None,
+                Err(e) => Some(e.message),
+            },
+            // refused by the running instance the same way
+            None if whitelisted(&process_name) => Some("process is whitelisted".to_string()),
+            None => toggle_efficiency_mode(process_id, enable)
+                .err()
+                .map(|e| e.to_string()),
+        };
+        outcomes.push(Outcome {
+            pid: process_id,
+            name: process_name.to_string_lossy().into_owned(),
+            error,
+        });
+    }
+
+    if json {
+        print_json(&outcomes);
+    } else {
+        for Outcome { pid, name, error } in &outcomes {
+            match error {
+                None => println!("{action}d {pid} {name}"),
+                Some(e) => println!("failed to {action} {pid} {name}: {e}"),
+            }
+        }
+    }
+
+    if let (Some(target), true) = (target, outcomes.is_empty()) {
+        eprintln!("no process matches {target}");
+        return Ok(false);
+    }
+    Ok(outcomes.iter().all(|outcome| outcome.error.is_none()))
+}
+
+pub async fn explain(sources: &Sources, target: &Target, json: bool) -> Result<bool> {
+    let layered = load_whitelist(sources).await?;
+    let config = &layered.config;
+
+    let triggers = [
+        (config.listen_new_process.enabled, Trigger::Started),
+        (config.listen_foreground_events.enabled, Trigger::Foreground),
+        (config.listen_foreground_events.enabled, Trigger::Background),
+        (
+            config.throttle_all_startup,
+            Trigger::Sweep(Action::Throttle),
+        ),
+    ];
+
+    let procs = Processes::try_new()?.collect::<Vec<_>>();
+    let tree = ProcTree::from(procs.iter());
+    let foreground_pid = foreground_process_id();
+    let fullscreen = fullscreen_running();
+
+    let explanations = procs
+        .iter()
+        .filter(|process| match target {
+            Target::Pid(pid) => process.process_id == *pid,
+            Target::Name(name) => process.process_name == *name,
+        })
+        .map(|process| {
+            let name = process.process_name.to_string_lossy().into_owned();
+            let facts = Facts {
+                whitelisted: whitelisted(&process.process_name),
+                blacklisted: config.listen_new_process.blacklist.contains(&name),
+                in_foreground_tree: foreground_pid
+                    .is_some_and(|foreground| tree.is_in_tree(foreground, process.process_id)),
+                fullscreen,
+                mode: config.listen_new_process.mode,
+            };
+            Explanation {
+                pid: process.process_id,
+                whitelisted_by: whitelisted_by(&layered, &name),
+                foreground_pid,
+                traces: triggers
+                    .iter()
+                    .filter(|(enabled, _)| *enabled)
+                    .map(|(_, trigger)| decide(*trigger, &facts))
+                    .collect(),
+                name,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if explanations.is_empty() {
+        eprintln!("no process matches {target}");
+        return Ok(false);
+    }
+
+    if json {
+        print_json(&explanations);
+        return Ok(true);
+    }
+
+    for (i, explanation) in explanations.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print_explanation(explanation);
+    }
+    Ok(true)
+}
+
+fn print_explanation(
+    Explanation {
+        pid,
+        name,
+        whitelisted_by,
+        foreground_pid,
+        traces,
+    }: &Explanation,
+) {
+    println!("process {pid} {name}");
+    if traces.is_empty() {
+        println!("  left to Windows, no listener is enabled");
+    }
+
+    for Trace {
+        trigger,
+        steps,
+        rule,
+        decision,
+    } in traces
+    {
+        println!("  {trigger}:");
+        for (i, Step { check, matched }) in steps.iter().enumerate() {
+            let detail = match (*check, matched, whitelisted_by, foreground_pid) {
+                ("whitelisted", true, Some(entry), _) => format!(", by {entry}"),
+                ("in foreground tree", false, _, Some(foreground)) => {
+                    format!(", foreground is {foreground}")
+                }
+                _ => String::new(),
+            };
+            println!(
+                "    {}. {check}: {}{detail}",
+                i + 1,
+                if *matched { "yes" } else { "no" }
+            );
+        }
+        match rule {
+            Some(rule) => println!("    => {decision}, decided by `{rule}`"),
+            None => println!("    => {decision}, by default"),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cli::{Result, format_duration, print_json};
+use crate::decision::unix_millis;
+use crate::ipc::Client;
+
+/// forward `method` to the running instance, and print its result
+pub async fn forward(method: &str, params: Value, json: bool) -> Result<bool> {
+    let mut client = Client::connect()
+        .await
+        .map_err(|e| format!("RustyStar isn't running, or can't be reached: {e}"))?;
+
+    let result = match client.call(method, params).await? {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("failed to {method}: {}", e.message);
+            return Ok(false);
+        }
+    };
+
+    if json {
+        print_json(&result);
+        return Ok(true);
+    }
+    match method {
+        "pause" => match result["paused_seconds_left"].as_u64() {
+            Some(seconds) => println!("throttling paused for {seconds} seconds"),
+            None => println!("throttling paused until `RustyStar resume`"),
+        },
+        "resume" => println!("throttling resumed"),
+        "reload" => println!("configuration is valid, reloading"),
+        "restore" => println!("restored all processes but whitelisted ones"),
+        "decisions" => print_decisions(&result),
+        "report" => print_report(&result),
+        _ => println!("{result}"),
+    }
+    Ok(true)
+}
+
+fn print_decisions(decisions: &Value) {
+    let decisions = decisions.as_array().map(Vec::as_slice).unwrap_or_default();
+    if decisions.is_empty() {
+        println!("no decision matches");
+        return;
+    }
+
+    let now = unix_millis();
+    println!(
+        "{:>9} {:>7}  {:<24} {:<16} {:<8} {:<18} {:<8} {:>8}",
+        "AGO", "PID", "NAME", "TRIGGER", "ACTION", "REASON", "OUTCOME", "LATENCY"
+    );
+    for decision in decisions {
+        let text = |key: &str| decision[key].as_str().unwrap_or("-").to_string();
+        // sweeps and requests are serialized as `{"sweep": "throttle"}`
+        let trigger = match &decision["trigger"] {
+            Value::Object(trigger) => trigger
+                .iter()
+                .map(|(trigger, action)| format!("{trigger} {}", action.as_str().unwrap_or("")))
+                .collect(),
+            trigger => trigger.as_str().unwrap_or("-").to_string(),
+        };
+        let time = decision["time"].as_u64().unwrap_or(now);
+        let latency = decision["latency_us"].as_u64().unwrap_or(0) as f64 / 1000.0;
+        println!(
+            "{:>9} {:>7}  {:<24} {trigger:<16} {:<8} {:<18} {:<8} {:>6.1}ms",
+            format_duration(Duration::from_millis(now.saturating_sub(time))),
+            decision["pid"].as_u64().unwrap_or_default(),
+            text("name"),
+            text("action"),
+            text("reason"),
+            text("outcome"),
+            latency,
+        );
+    }
+}
+
+fn print_report(report: &Value) {
+    let now = unix_millis();
+    let ago = |time: u64| format_duration(Duration::from_millis(now.saturating_sub(time)));
+    let millis =
+        |value: &Value| format_duration(Duration::from_millis(value.as_u64().unwrap_or(0)));
+
+    println!("# RustyStar report");
+    println!();
+    match report["since"].as_u64() {
+        Some(since) => println!("Since {} ago.", ago(since)),
+        None => println!("Since RustyStar started."),
+    }
+    println!();
+
+    let executables = report["executables"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if executables.is_empty() {
+        println!("No process was seen.");
+        return;
+    }
+    println!(
+        "| Executable | Throttled | Boosted | Transitions | Failures | First seen | Last seen |"
+    );
+    println!("| --- | ---: | ---: | ---: | ---: | ---: | ---: |");
+    for executable in executables {
+        let count = |key: &str| executable[key].as_u64().unwrap_or_default();
+        let seen = |key: &str| executable[key].as_u64().map_or("-".to_string(), ago) + " ago";
+        println!(
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            executable["name"].as_str().unwrap_or("-"),
+            millis(&executable["throttled_ms"]),
+            millis(&executable["boosted_ms"]),
+            count("transitions"),
+            count("failures"),
+            seen("first_seen"),
+            seen("last_seen"),
+        );
+    }
+}
+
+/// tell the user that RustyStar is running already, e.g. after launching it twice
+pub async fn already_running() {
+    let status = match Client::connect().await {
+        Ok(mut client) => client.call("status", Value::Null).await.ok(),
+        Err(_) => None,
+    };
+    let message = match status {
+        Some(Ok(status)) if status["paused"] == true => format!(
+            "RustyStar is already running (process {}), throttling is paused.",
+            status["pid"]
+        ),
+        Some(Ok(status)) => format!("RustyStar is already running (process {}).", status["pid"]),
+        _ => "RustyStar is already running.".to_string(),
+    };
+    println!("{message}");
+
+    // without a console, a message box is the only thing visible
+    #[cfg(feature = "hide-to-tray")]
+    unsafe {
+        use windows::Win32::UI::WindowsAndMessaging::{MB_ICONINFORMATION, MB_OK, MessageBoxW};
+        use windows::core::{HSTRING, w};
+
+        MessageBoxW(
+            None,
+            &HSTRING::from(message),
+            w!("RustyStar"),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::cli::{Result, print_json};
+use crate::config::layer::Sources;
+use crate::config::{ListenNewProcessMode, PORTABLE_DIR, log_file};
+use crate::ipc::endpoint;
+use crate::utils::instance_running;
+
+#[derive(Serialize)]
+struct Status {
+    version: &'static str,
+    running: bool,
+    portable: bool,
+    config: PathBuf,
+    policy: PathBuf,
+    log: PathBuf,
+    /// endpoint of the control API
+    control: Option<PathBuf>,
+    /// `None` if configuration is invalid
+    settings: Option<Settings>,
+}
+
+#[derive(Serialize)]
+struct Settings {
+    listen_foreground_events: bool,
+    listen_new_process: bool,
+    listen_new_process_mode: ListenNewProcessMode,
+    throttle_all_startup: bool,
+    whitelisted: usize,
+}
+
+pub async fn status(sources: &Sources, json: bool) -> Result<bool> {
+    let settings = sources.load().await.ok().map(|layered| {
+        let config = layered.config;
+        Settings {
+            listen_foreground_events: config.listen_foreground_events.enabled,
+            listen_new_process: config.listen_new_process.enabled,
+            listen_new_process_mode: config.listen_new_process.mode,
+            throttle_all_startup: config.throttle_all_startup,
+            whitelisted: config.whitelist().len(),
+        }
+    });
+
+    let status = Status {
+        version: env!("CARGO_PKG_VERSION"),
+        running: instance_running(),
+        portable: PORTABLE_DIR.is_some(),
+        config: sources.user.clone(),
+        policy: sources.policy.clone(),
+        log: log_file(),
+        control: endpoint().ok(),
+        settings,
+    };
+
+    if json {
+        print_json(&status);
+        return Ok(true);
+    }
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    println!("RustyStar {}", status.version);
+    println!("running:        {}", yes_no(status.running));
+    println!("portable:       {}", yes_no(status.portable));
+    println!("config:         {}", status.config.display());
+    println!("policy:         {}", status.policy.display());
+    println!("log:            {}", status.log.display());
+    if let Some(control) = &status.control {
+        println!("control API:    {}", control.display());
+    }
+    match status.settings {
+        Some(settings) => {
+            println!(
+                "foreground:     {}",
+                yes_no(settings.listen_foreground_events)
+            );
+            println!(
+                "new processes:  {} ({:?})",
+                yes_no(settings.listen_new_process),
+                settings.listen_new_process_mode
+            );
+            println!("throttle all:   {}", yes_no(settings.throttle_all_startup));
+            println!("whitelisted:    {} process names", settings.whitelisted);
+        }
+        None => println!("config is invalid, run `RustyStar config check` for details"),
+    }
+    Ok(true)
+}
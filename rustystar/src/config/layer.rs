@@ -0,0 +1,608 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+use spdlog::warn;
+use toml::{Table, Value};
+
+use crate::config::merge::default_keys;
+use crate::config::migrate::migrate;
+use crate::config::validate::{Report, known_key};
+use crate::config::{Config, read_config};
+
+type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
+
+const ENV_PREFIX: &str = "RUSTYSTAR_";
+
+/// where the value of a key came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    User(PathBuf),
+    Env(String),
+    Cli,
+    Policy(PathBuf),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::User(path) => write!(f, "user config {}", path.display()),
+            Origin::Env(var) => write!(f, "environment {var}"),
+            Origin::Cli => write!(f, "command line --set"),
+            Origin::Policy(path) => write!(f, "machine policy {}", path.display()),
+        }
+    }
+}
+
+/// configuration sources, from the lowest to the highest precedence:
+///
+/// built-in defaults, user config, `RUSTYSTAR_*` environment variables, `--set` overrides.
+///
+/// The machine policy is applied last, keys it sets cannot be changed by users,
+/// lists it sets replace the ones of users.
+#[derive(Debug, Clone)]
+pub struct Sources {
+    pub policy: PathBuf,
+    pub user: PathBuf,
+    /// `key=value` pairs from command line
+    pub overrides: Vec<(String, String)>,
+}
+
+/// merged configuration, with the origin of every key
+pub struct Layered {
+    pub config: Config,
+    /// dotted key, e.g. `listen_new_process.mode`
+    pub origins: BTreeMap<String, Origin>,
+    /// files pulled in by `include`, in merge order
+    pub includes: Vec<PathBuf>,
+    table: Table,
+}
+
+impl Sources {
+    /// `config` replaces the default location of user config
+    pub async fn new(config: Option<PathBuf>, overrides: Vec<(String, String)>) -> Result<Self> {
+        let user = match config {
+            Some(path) => path,
+            None => Config::config_path().await?,
+        };
+
+        Ok(Self {
+            policy: Config::policy_path(),
+            user,
+            overrides,
+        })
+    }
+
+    /// config files to watch for changes
+    pub fn files(&self) -> Vec<PathBuf> {
+        vec![self.policy.clone(), self.user.clone()]
+    }
+
+    /// config files and files included by them in `layered`
+    pub fn watched_files(&self, layered: &Layered) -> Vec<PathBuf> {
+        let mut files = self.files();
+        files.extend(layered.includes.iter().cloned());
+        files
+    }
+
+    pub async fn load(&self) -> Result<Layered> {
+        let mut layered = Layered::new()?;
+
+        let user_layers = read_with_includes(&self.user, true, &mut vec![], &mut vec![]).await?;
+        // lists of included packs add up, instead of replacing each other
+        let join_lists = user_layers.len() > 1;
+        for (path, layer) in user_layers {
+            if path != self.user {
+                layered.includes.push(path.clone());
+            }
+            layered.merge(layer, &Origin::User(path), join_lists)?;
+        }
+
+        let mut env_vars = std::env::vars()
+            .filter_map(|(var, value)| Some((var.strip_prefix(ENV_PREFIX)?.to_string(), value)))
+            .collect::<Vec<_>>();
+        env_vars.sort();
+        for (name, value) in env_vars {
+            let key = name.to_lowercase().replace("__", ".");
+            let var = format!("{ENV_PREFIX}{name}");
+            if !known_key(&key) {
+                warn!("ignoring {var}: unknown key `{key}`");
+                continue;
+            }
+
+            layered
+                .merge(single_key(&key, &value), &Origin::Env(var.clone()), false)
+                .map_err(|e| format!("invalid {var}: {e}"))?;
+        }
+
+        for (key, value) in &self.overrides {
+            if !known_key(key) {
+                Err(format!("--set {key}: unknown key"))?;
+            }
+            layered
+                .merge(single_key(key, value), &Origin::Cli, false)
+                .map_err(|e| format!("invalid --set {key}={value}: {e}"))?;
+        }
+
+        // policy files are joined first, so users can't add to lists the policy sets
+        let mut policy = Table::new();
+        let mut policy_origins = BTreeMap::new();
+        for (path, layer) in
+            read_with_includes(&self.policy, false, &mut vec![], &mut vec![]).await?
+        {
+            if path != self.policy {
+                layered.includes.push(path.clone());
+            }
+            let origin = Origin::Policy(path);
+            merge_table(&mut policy, layer, "", &origin, true, &mut policy_origins);
+        }
+        layered.merge(policy, &Origin::Policy(self.policy.clone()), false)?;
+        layered.origins.extend(policy_origins);
+
+        Ok(layered)
+    }
+}
+
+impl Layered {
+    fn new() -> Result<Self> {
+        let config = Config::default();
+        let Value::Table(table) = Value::try_from(&config)? else {
+            unreachable!("config is serialized as a table");
+        };
+
+        let mut origins = BTreeMap::new();
+        for key in leaf_keys(&table, "") {
+            origins.insert(key, Origin::Default);
+        }
+
+        Ok(Self {
+            config,
+            origins,
+            includes: vec![],
+            table,
+        })
+    }
+
+    /// merge `layer` on top, lists are extended instead of replaced if `join_lists`
+    fn merge(&mut self, layer: Table, origin: &Origin, join_lists: bool) -> Result<()> {
+        let mut table = self.table.clone();
+        merge_table(&mut table, layer, "", origin, join_lists, &mut self.origins);
+
+        self.config = Value::Table(table.clone()).try_into()?;
+        self.table = table;
+        Ok(())
+    }
+
+    /// effective configuration as TOML, with origin of every key if `with_origin`
+    pub fn render(&self, with_origin: bool) -> Result<String> {
+        if !with_origin {
+            return Ok(toml::to_string_pretty(&self.config)?);
+        }
+
+        let mut rendered = String::new();
+        for (key, origin) in &self.origins {
+            let value = key
+                .split('.')
+                .try_fold(&self.table, |table, part| match table.get(part) {
+                    Some(Value::Table(sub)) => Ok(sub),
+                    Some(value) => Err(value),
+                    None => unreachable!("origin is recorded for existing keys"),
+                })
+                .err()
+                .map(Value::to_string)
+                .unwrap_or_default();
+            rendered += &format!("{key} = {value} # {origin}\n");
+        }
+
+        Ok(rendered)
+    }
+}
+
+fn merge_table(
+    target: &mut Table,
+    layer: Table,
+    prefix: &str,
+    origin: &Origin,
+    join_lists: bool,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (key, value) in layer {
+        let path = format!("{prefix}{key}");
+
+        match (target.get_mut(&key), value) {
+            (Some(Value::Table(sub)), Value::Table(layer)) => {
+                merge_table(sub, layer, &format!("{path}."), origin, join_lists, origins);
+            }
+            (Some(Value::Array(current)), Value::Array(entries)) if join_lists => {
+                for entry in entries {
+                    if !current.contains(&entry) {
+                        current.push(entry);
+                    }
+                }
+                origins.insert(path, origin.clone());
+            }
+            (_, value) => {
+                target.insert(key, value);
+                origins.insert(path, origin.clone());
+            }
+        }
+    }
+}
+
+fn leaf_keys(table: &Table, prefix: &str) -> Vec<String> {
+    table
+        .iter()
+        .flat_map(|(key, value)| match value {
+            Value::Table(sub) => leaf_keys(sub, &format!("{prefix}{key}.")),
+            _ => vec![format!("{prefix}{key}")],
+        })
+        .collect()
+}
+
+/// read a config file and the files it includes, as layers in merge order
+///
+/// Included files come before the including one, in the listed order.
+/// A file included more than once is only merged the first time.
+/// Only the user's config file is migrated, if `user`.
+async fn read_with_includes(
+    path: &Path,
+    user: bool,
+    stack: &mut Vec<PathBuf>,
+    loaded: &mut Vec<PathBuf>,
+) -> Result<Vec<(PathBuf, Table)>> {
+    let Some(layer) = read_file_layer(path, user && stack.is_empty()).await? else {
+        if stack.is_empty() {
+            return Ok(vec![]);
+        }
+        return Err(format!("included file {} not found", path.display()).into());
+    };
+
+    let canonical = std::fs::canonicalize(path)?;
+    if let Some(start) = stack.iter().position(|p| *p == canonical) {
+        let cycle = stack[start..]
+            .iter()
+            .chain([&canonical])
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+        Err(format!("include cycle: {}", cycle.join(" -> ")))?;
+    }
+    if loaded.contains(&canonical) {
+        return Ok(vec![]);
+    }
+
+    let includes = match layer.get("include") {
+        Some(Value::Array(includes)) => includes
+            .iter()
+            .filter_map(Value::as_str)
+            .map(PathBuf::from)
+            .collect(),
+        _ => vec![],
+    };
+
+    stack.push(canonical.clone());
+    let mut layers = vec![];
+    let base = path.parent().unwrap_or(Path::new("."));
+    for include in includes {
+        let include = base.join(include);
+        layers.extend(Box::pin(read_with_includes(&include, user, stack, loaded)).await?);
+    }
+    stack.pop();
+
+    loaded.push(canonical);
+    layers.push((path.to_path_buf(), layer));
+    Ok(layers)
+}
+
+/// read a config file as a layer, `None` if not existing
+///
+/// Outdated user config is migrated in memory, the file is left untouched.
+/// Included files and the policy are fragments, they are never migrated:
+/// converting a legacy `whitelist` requires the whole list.
+/// Values of the user config equal to their default are left out, see [`default_keys`].
+async fn read_file_layer(path: &Path, user_config: bool) -> Result<Option<Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut content = read_config(path).await?;
+    if user_config && let Some((_, migrated)) = migrate(&content)? {
+        content = migrated;
+    }
+
+    let report = Report::new(path, &content);
+    if report.has_errors() {
+        Err(format!("invalid configuration:\n{report}"))?;
+    }
+    for diagnostic in &report.diagnostics {
+        warn!("{}", report.render(diagnostic));
+    }
+
+    let mut layer = toml::from_str(&content)?;
+    if user_config {
+        for key in default_keys(&layer)? {
+            remove_key(&mut layer, &key);
+        }
+    }
+    Ok(Some(layer))
+}
+
+fn remove_key(table: &mut Table, key: &str) {
+    match key.split_once('.') {
+        Some((parent, rest)) => {
+            if let Some(Value::Table(sub)) = table.get_mut(parent) {
+                remove_key(sub, rest);
+            }
+        }
+        None => _ = table.remove(key),
+    }
+}
+
+/// nest `value` of a dotted key, e.g. `listen_new_process.mode`
+///
+/// `value` is parsed as TOML, and taken as a string if that fails.
+fn single_key(key: &str, value: &str) -> Table {
+    let value = toml::from_str::<Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()));
+
+    let mut parts = key.rsplit('.');
+    let leaf = parts.next().unwrap_or_default();
+    parts.fold(
+        Table::from_iter([(leaf.to_string(), value)]),
+        |table, parent| Table::from_iter([(parent.to_string(), Value::Table(table))]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{Layered, Origin, Sources, single_key};
+    use crate::config::merge::fill_missing;
+
+    /// write `files` into a fresh directory, returns the sources of its `config.toml`
+    fn sources_with(name: &str, files: &[(&str, &str)]) -> Sources {
+        let dir = std::env::temp_dir().join(format!("rustystar-{name}-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        Sources {
+            policy: dir.join("policy.toml"),
+            user: dir.join("config.toml"),
+            overrides: vec![],
+        }
+    }
+
+    fn file_name(path: &Path) -> &str {
+        path.file_name().unwrap().to_str().unwrap()
+    }
+
+    #[compio::test]
+    async fn includes_are_merged_in_order() {
+        let sources = sources_with(
+            "include",
+            &[
+                (
+                    "config.toml",
+                    "include = [\"packs/dev.toml\", \"games.toml\"]\nwhitelist_extra = [\"mine.exe\"]\n",
+                ),
+                (
+                    "packs/dev.toml",
+                    "include = [\"../games.toml\"]\nwhitelist_extra = [\"code.exe\"]\nthrottle_all_startup = true\n",
+                ),
+                (
+                    "games.toml",
+                    "whitelist_extra = [\"game.exe\"]\nthrottle_all_startup = false\n",
+                ),
+            ],
+        );
+
+        let layered = sources.load().await.unwrap();
+        assert_eq!(
+            layered.config.whitelist_extra,
+            ["game.exe", "code.exe", "mine.exe"]
+        );
+        // `games.toml` is only merged once, before `dev.toml` which includes it
+        assert!(layered.config.throttle_all_startup);
+        assert_eq!(
+            layered
+                .includes
+                .iter()
+                .map(|p| file_name(p))
+                .collect::<Vec<_>>(),
+            ["games.toml", "dev.toml"]
+        );
+    }
+
+    #[compio::test]
+    async fn include_cycles_are_rejected() {
+        let sources = sources_with(
+            "cycle",
+            &[
+                ("config.toml", "include = [\"a.toml\"]\n"),
+                ("a.toml", "include = [\"b.toml\"]\n"),
+                ("b.toml", "include = [\"a.toml\"]\n"),
+            ],
+        );
+
+        let error = sources.load().await.err().unwrap().to_string();
+        assert!(error.starts_with("include cycle: "));
+        assert!(error.ends_with("a.toml"));
+    }
+
+    #[compio::test]
+    async fn includes_set_keys_left_at_defaults() {
+        let user = fill_missing("include = [\"pack.toml\"]\ndry_run = true\n")
+            .unwrap()
+            .unwrap();
+        let sources = sources_with(
+            "include-scalars",
+            &[
+                ("config.toml", &user),
+                (
+                    "pack.toml",
+                    "throttle_all_startup = false\ndry_run = false\n",
+                ),
+            ],
+        );
+
+        let layered = sources.load().await.unwrap();
+        // filled in as `throttle_all_startup = true`
+        assert!(!layered.config.throttle_all_startup);
+        assert!(
+            matches!(&layered.origins["throttle_all_startup"], Origin::User(path) if file_name(path) == "pack.toml")
+        );
+        // set by the user
+        assert!(layered.config.dry_run);
+    }
+
+    #[compio::test]
+    async fn only_user_config_is_migrated() {
+        let sources = sources_with(
+            "fragment",
+            &[
+                (
+                    "config.toml",
+                    "include = [\"pack.toml\"]\nwhitelist = [\"explorer.exe\"]\n",
+                ),
+                ("pack.toml", "whitelist = [\"code.exe\"]\n"),
+            ],
+        );
+        let error = sources.load().await.err().unwrap().to_string();
+        assert!(error.contains("legacy key `whitelist`"));
+
+        let sources = sources_with(
+            "migrated",
+            &[(
+                "config.toml",
+                "whitelist = [\"explorer.exe\", \"code.exe\"]\n",
+            )],
+        );
+        let layered = sources.load().await.unwrap();
+        assert_eq!(layered.config.whitelist_extra, ["code.exe"]);
+        assert!(
+            layered
+                .config
+                .whitelist_remove
+                .contains(&"lsass.exe".to_string())
+        );
+    }
+
+    #[compio::test]
+    async fn missing_include_is_an_error() {
+        let sources = sources_with("missing", &[("config.toml", "include = [\"gone.toml\"]\n")]);
+        assert!(sources.load().await.is_err());
+    }
+
+    #[compio::test]
+    async fn default_values_and_policy_lists() {
+        let user = fill_missing("dry_run = true\nwhitelist_extra = [\"mine.exe\"]\n")
+            .unwrap()
+            .unwrap();
+        let sources = sources_with(
+            "policy",
+            &[
+                ("config.toml", &user),
+                (
+                    "policy.toml",
+                    "include = [\"pack.toml\"]\nwhitelist_extra = [\"code.exe\"]\n",
+                ),
+                ("pack.toml", "whitelist_extra = [\"term.exe\"]\n"),
+            ],
+        );
+
+        let layered = sources.load().await.unwrap();
+        assert_eq!(layered.config.whitelist_extra, ["term.exe", "code.exe"]);
+
+        let origins = &layered.origins;
+        assert_eq!(origins["system_process"], Origin::Default);
+        assert_eq!(origins["listen_new_process.mode"], Origin::Default);
+        assert!(matches!(&origins["dry_run"], Origin::User(_)));
+        assert!(
+            matches!(&origins["whitelist_extra"], Origin::Policy(path) if file_name(path) == "policy.toml")
+        );
+    }
+
+    #[test]
+    fn layers_are_merged_with_origin() {
+        let user = Origin::User(PathBuf::from("config.toml"));
+        let policy = Origin::Policy(PathBuf::from("policy.toml"));
+
+        let mut layered = Layered::new().unwrap();
+        layered
+            .merge(
+                toml::from_str(
+                    "throttle_all_startup = false\nwhitelist_extra = [\"a.exe\"]\n[listen_new_process]\nmode = \"blacklist_only\"",
+                )
+                .unwrap(),
+                &user,
+                false,
+            )
+            .unwrap();
+        layered
+            .merge(
+                single_key("listen_new_process.enabled", "false"),
+                &Origin::Cli,
+                false,
+            )
+            .unwrap();
+        layered
+            .merge(
+                toml::from_str("throttle_all_startup = true\nwhitelist_extra = [\"b.exe\"]")
+                    .unwrap(),
+                &policy,
+                false,
+            )
+            .unwrap();
+
+        let config = &layered.config;
+        assert!(config.throttle_all_startup);
+        assert_eq!(config.whitelist_extra, ["b.exe"]);
+        assert!(!config.listen_new_process.enabled);
+
+        let origins = &layered.origins;
+        assert_eq!(origins["throttle_all_startup"], policy);
+        assert_eq!(origins["whitelist_extra"], policy);
+        assert_eq!(origins["listen_new_process.mode"], user);
+        assert_eq!(origins["listen_new_process.enabled"], Origin::Cli);
+        assert_eq!(origins["system_process"], Origin::Default);
+
+        let rendered = layered.render(true).unwrap();
+        assert!(rendered.contains("listen_new_process.mode = \"blacklist_only\" # user config"));
+    }
+
+    #[test]
+    fn invalid_layer_is_rejected() {
+        let mut layered = Layered::new().unwrap();
+        let before = layered.config.clone();
+
+        let result = layered.merge(
+            single_key("throttle_all_startup", "yes"),
+            &Origin::Cli,
+            false,
+        );
+        assert!(result.is_err());
+        assert_eq!(layered.config, before);
+    }
+
+    #[test]
+    fn override_values() {
+        assert_eq!(
+            single_key("listen_new_process.mode", "blacklist_only").to_string(),
+            "[listen_new_process]\nmode = \"blacklist_only\"\n"
+        );
+        assert_eq!(
+            single_key("whitelist_extra", "[\"a.exe\"]").to_string(),
+            "whitelist_extra = [\"a.exe\"]\n"
+        );
+    }
+}
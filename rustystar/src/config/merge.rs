@@ -1,49 +1,181 @@
 use std::error::Error;
 use std::fmt::Write;
 
-use documented::DocumentedFields;
-use toml_edit::{DocumentMut, Item, Table};
+use serde_json::Value;
+use toml_edit::{Decor, DocumentMut, Item, Table};
 
-use crate::config::{Config, ListenForegroundEvents, ListenNewProcess};
+use crate::config::Config;
+use crate::config::schema::{self, SCHEMA, SCHEMA_URL};
 
 type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
 
 pub fn append_comments(toml: &str) -> Result<String> {
     let mut new_doc = toml.parse::<DocumentMut>()?;
 
-    comment_sub_struct(new_doc.as_table_mut(), Config::get_field_docs);
+    comment_table(new_doc.as_table_mut(), &SCHEMA, &SCHEMA);
     Ok(new_doc.to_string())
 }
 
-fn comment_sub_struct(
-    t: &mut Table,
-    get_field: fn(String) -> Result<&'static str, documented::Error>,
-) {
-    for (mut field, item) in t.iter_mut() {
-        let Ok(doc) = get_field(field.to_string()).map(fold_doc) else {
+/// default configuration with doc comments, as written for new users
+///
+/// `#:schema` lets editors with taplo complete and check options.
+pub fn default_document() -> Result<String> {
+    Ok(format!(
+        "#:schema {SCHEMA_URL}\n\n{}",
+        commented_defaults()?
+    ))
+}
+
+fn commented_defaults() -> Result<String> {
+    let doc = append_comments(&toml::to_string_pretty(&Config::default())?)?;
+    Ok(doc.trim_start().to_string())
+}
+
+/// dotted keys of `layer` which hold their default value
+///
+/// In `config.toml` they count as defaults, most were filled in by RustyStar,
+/// so they don't hide values of included files.
+pub fn default_keys(layer: &toml::Table) -> Result<Vec<String>> {
+    let toml::Value::Table(defaults) = toml::Value::try_from(Config::default())? else {
+        unreachable!("config is serialized as a table");
+    };
+    let mut keys = vec![];
+    equal_keys(layer, &defaults, "", &mut keys);
+    Ok(keys)
+}
+
+fn equal_keys(layer: &toml::Table, defaults: &toml::Table, prefix: &str, keys: &mut Vec<String>) {
+    for (key, value) in layer {
+        let Some(default) = defaults.get(key) else {
             continue;
         };
+        match (value, default) {
+            (toml::Value::Table(sub), toml::Value::Table(defaults)) => {
+                equal_keys(sub, defaults, &format!("{prefix}{key}."), keys);
+            }
+            _ if value == default => keys.push(format!("{prefix}{key}")),
+            _ => {}
+        }
+    }
+}
+
+/// insert keys missing in user's `content` with their default value and doc comments.
+///
+/// Everything else in the document is kept as is, returns `None` if nothing was missing.
+pub fn fill_missing(content: &str) -> Result<Option<String>> {
+    let mut doc = content.parse::<DocumentMut>()?;
+    // the `#:schema` directive belongs on top, it's not attached to any key
+    let defaults = commented_defaults()?.parse::<DocumentMut>()?;
+
+    let mut next_position = max_position(doc.as_table()) + 1;
+    if insert_missing(doc.as_table_mut(), defaults.as_table(), &mut next_position) {
+        Ok(Some(doc.to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn insert_missing(target: &mut Table, defaults: &Table, next_position: &mut isize) -> bool {
+    let mut changed = false;
+
+    for (key, default) in defaults.iter() {
+        match (target.get_mut(key), default) {
+            (None, _) => {
+                let mut item = default.clone();
+                if let Item::Table(t) = &mut item {
+                    // append new tables after the existing ones
+                    reposition(t, next_position);
+                }
+                // Table::key keeps the doc comments attached to the key
+                let key = defaults.key(key).expect("key exists").clone();
+                target.insert_formatted(&key, item);
+                changed = true;
+            }
+            (Some(Item::Table(t)), Item::Table(d)) => {
+                changed |= insert_missing(t, d, next_position);
+            }
+            _ => {}
+        }
+    }
+
+    changed
+}
+
+fn reposition(t: &mut Table, next_position: &mut isize) {
+    t.set_position(*next_position);
+    *next_position += 1;
+
+    for (_, item) in t.iter_mut() {
+        if let Item::Table(sub) = item {
+            reposition(sub, next_position);
+        }
+    }
+}
+
+fn max_position(t: &Table) -> isize {
+    t.iter()
+        .filter_map(|(_, item)| item.as_table())
+        .map(|sub| sub.position().unwrap_or_default().max(max_position(sub)))
+        .max()
+        .unwrap_or_default()
+}
+
+/// prefix every key of `t` with doc comments from its `schema`, recursively
+fn comment_table(t: &mut Table, schema: &Value, root: &Value) {
+    for (mut key, item) in t.iter_mut() {
+        let Some(property) = schema::property(schema, key.get(), root) else {
+            continue;
+        };
+        let doc = doc_comment(property, root);
 
         match item {
-            Item::Value(_) => field.leaf_decor_mut().set_prefix((doc + "\n").trim_start()),
-            Item::Table(t) => {
-                let decor = t.decor_mut();
-                let prefix = decor.prefix().unwrap();
-                decor.set_prefix(doc + prefix.as_str().unwrap_or_default());
-
-                match field.get() {
-                    "listen_foreground_events" => {
-                        comment_sub_struct(t, ListenForegroundEvents::get_field_docs);
-                    }
-                    "listen_new_process" => {
-                        comment_sub_struct(t, ListenNewProcess::get_field_docs);
+            Item::Value(_) => key.leaf_decor_mut().set_prefix((doc + "\n").trim_start()),
+            Item::Table(sub) => {
+                prepend_doc(sub.decor_mut(), doc);
+                comment_table(sub, property, root);
+            }
+            Item::ArrayOfTables(array) => {
+                let items = &schema::resolve(property, root)["items"];
+                for (index, sub) in array.iter_mut().enumerate() {
+                    if index == 0 {
+                        prepend_doc(sub.decor_mut(), doc.clone());
                     }
-                    _ => (),
+                    comment_table(sub, items, root);
                 }
             }
-            _ => {}
+            Item::None => {}
+        }
+    }
+}
+
+/// put `doc` between the blank lines before a table and its header
+fn prepend_doc(decor: &mut Decor, doc: String) {
+    let prefix = decor
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or_default();
+    decor.set_prefix(format!("{prefix}{}\n", doc.trim_start()));
+}
+
+/// description of a key, followed by the allowed values of enums
+fn doc_comment(property: &Value, root: &Value) -> String {
+    let mut doc = fold_doc(property["description"].as_str().unwrap_or_default());
+
+    let resolved = schema::resolve(property, root);
+    // enums may also be listed in arrays
+    let values = match resolved["type"].as_str() {
+        Some("array") => schema::resolve(&resolved["items"], root),
+        _ => resolved,
+    };
+    for variant in values["oneOf"].as_array().into_iter().flatten() {
+        let _ = write!(&mut doc, "\n# - {}", variant["const"]);
+        if let Some(description) = variant["description"].as_str() {
+            let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+            let _ = write!(&mut doc, ": {description}");
         }
     }
+
+    doc
 }
 
 fn fold_doc(doc: &str) -> String {
@@ -55,3 +187,143 @@ fn fold_doc(doc: &str) -> String {
             s
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+    use toml_edit::DocumentMut;
+
+    use super::{comment_table, default_document, default_keys, fill_missing};
+    use crate::config::assert_snapshot;
+
+    fn snapshot(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/config/snapshots")
+            .join(name)
+    }
+
+    #[test]
+    fn default_document_snapshot() {
+        assert_snapshot(&snapshot("default.toml"), &default_document().unwrap());
+    }
+
+    #[test]
+    fn filled_document_snapshot() {
+        let content = "\
+# my own comment
+whitelist_extra = [\"foo.exe\"]
+
+[listen_new_process]
+mode = \"blacklist_only\"
+";
+        assert_snapshot(
+            &snapshot("filled.toml"),
+            &fill_missing(content).unwrap().unwrap(),
+        );
+    }
+
+    #[test]
+    fn arrays_of_tables_and_enums_are_commented() {
+        let schema = json!({
+            "properties": {
+                "rules": {
+                    "description": "per process rules",
+                    "type": "array",
+                    "items": { "$ref": "#/$defs/Rule" }
+                }
+            },
+            "$defs": {
+                "Rule": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "description": "process name", "type": "string" },
+                        "action": {
+                            "description": "what to do",
+                            "oneOf": [
+                                { "const": "throttle", "description": "enable EcoQoS" },
+                                { "const": "ignore" }
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut doc =
+            "[[rules]]\nname = \"a.exe\"\naction = \"ignore\"\n\n[[rules]]\nname = \"b.exe\"\n"
+                .parse::<DocumentMut>()
+                .unwrap();
+        comment_table(doc.as_table_mut(), &schema, &schema);
+
+        assert_eq!(
+            doc.to_string(),
+            "# per process rules
+[[rules]]
+# process name
+name = \"a.exe\"
+# what to do
+# - \"throttle\": enable EcoQoS
+# - \"ignore\"
+action = \"ignore\"
+
+[[rules]]
+# process name
+name = \"b.exe\"
+"
+        );
+    }
+
+    #[test]
+    fn complete_document_is_untouched() {
+        let content = default_document().unwrap();
+        assert_eq!(fill_missing(&content).unwrap(), None);
+
+        let edited = content.replace(
+            "throttle_all_startup = true",
+            "throttle_all_startup = false",
+        ) + "\n# trailing user comment\n";
+        assert_eq!(fill_missing(&edited).unwrap(), None);
+    }
+
+    #[test]
+    fn missing_keys_are_inserted_with_comments() {
+        let content = "\
+# my own comment
+whitelist_extra = [\"foo.exe\"] # keep foo
+
+[listen_new_process]
+# enabled = false
+mode = \"blacklist_only\"
+";
+        let filled = fill_missing(content).unwrap().expect("keys are missing");
+
+        assert!(filled.starts_with(content.lines().next().unwrap()));
+        assert!(filled.contains("whitelist_extra = [\"foo.exe\"] # keep foo"));
+        assert!(filled.contains("# enabled = false"));
+        assert!(filled.contains("mode = \"blacklist_only\""));
+        assert!(filled.contains(
+            "# on startup, throttle all processes to EcoQoS\nthrottle_all_startup = true"
+        ));
+        assert!(filled.contains("# listen foreground window change events\nenabled = true"));
+
+        let config: crate::config::Config = toml::from_str(&filled).unwrap();
+        assert_eq!(config.whitelist_extra, ["foo.exe"]);
+        assert!(config.listen_new_process.enabled);
+
+        assert_eq!(fill_missing(&filled).unwrap(), None);
+    }
+
+    #[test]
+    fn default_values_are_recognized() {
+        let filled = fill_missing("throttle_all_startup = false\n")
+            .unwrap()
+            .unwrap();
+        let keys = default_keys(&toml::from_str(&filled).unwrap()).unwrap();
+
+        assert!(keys.contains(&"system_process".to_string()));
+        assert!(keys.contains(&"listen_new_process.mode".to_string()));
+        assert!(!keys.contains(&"throttle_all_startup".to_string()));
+    }
+}
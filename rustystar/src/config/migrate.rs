@@ -0,0 +1,255 @@
+use std::error::Error;
+
+use spdlog::info;
+use toml_edit::{Array, DocumentMut, Item, Key, Table, Value, value};
+
+type Result<T, E = Box<dyn Error + Send + Sync>> = std::result::Result<T, E>;
+
+/// bump this and append a step to `MIGRATIONS` whenever the layout changes
+pub const CURRENT_VERSION: u32 = 2;
+
+const VERSION_KEY: &str = "config_version";
+
+type Migration = fn(&mut DocumentMut);
+
+/// `MIGRATIONS[n]` upgrades a document from version `n` to `n + 1`
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+/// built-in whitelist up to version 1, must not be changed
+const V1_WHITELIST: [&str; 20] = [
+    "RustyStar.exe",
+    "explorer.exe",
+    "dwm.exe",
+    "csrss.exe",
+    "svchost.exe",
+    "Taskmgr.exe",
+    "smss.exe",
+    "ChsIME.exe",
+    "ctfmon.exe",
+    "WUDFRd.exe",
+    "WUDFHost.exe",
+    "msedge.exe",
+    "ApplicationFrameHost.exe",
+    "[System Process]",
+    "System",
+    "Registry",
+    "wininit.exe",
+    "services.exe",
+    "lsass.exe",
+    "SecurityHealthService.exe",
+];
+
+/// upgrade `content` to [`CURRENT_VERSION`], keeping user values and comments
+///
+/// returns the version it was upgraded from and the new document,
+/// or `None` if `content` is up to date already.
+pub fn migrate(content: &str) -> Result<Option<(u32, String)>> {
+    let mut doc = content.parse::<DocumentMut>()?;
+
+    let version = match doc.get(VERSION_KEY) {
+        None => 0,
+        Some(item) => item
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or(format!("`{VERSION_KEY}` must be a non-negative integer"))?,
+    };
+    if version > CURRENT_VERSION {
+        Err(format!(
+            "config version {version} is newer than supported version {CURRENT_VERSION}, please upgrade RustyStar"
+        ))?;
+    }
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("migrating config from version {from} to {}", from + 1);
+        migration(&mut doc);
+        set_version(doc.as_table_mut(), from as u32 + 1);
+    }
+
+    Ok(Some((version, doc.to_string())))
+}
+
+fn set_version(root: &mut Table, version: u32) {
+    if let Some(item) = root.get_mut(VERSION_KEY) {
+        let decor = item.as_value().map(|v| v.decor().clone());
+        *item = value(i64::from(version));
+        if let (Some(decor), Some(v)) = (decor, item.as_value_mut()) {
+            *v.decor_mut() = decor;
+        }
+        return;
+    }
+
+    // keep the version on top of the document
+    let entries = root
+        .iter()
+        .map(|(key, item)| (root.key(key).expect("key exists").clone(), item.clone()))
+        .collect::<Vec<_>>();
+    root.clear();
+
+    let mut key = Key::new(VERSION_KEY);
+    key.leaf_decor_mut()
+        .set_prefix("# version of config layout, managed by RustyStar\n");
+    root.insert_formatted(&key, value(i64::from(version)));
+    for (key, item) in entries {
+        root.insert_formatted(&key, item);
+    }
+}
+
+/// unversioned documents predate `config_version`, the layout is the same otherwise
+fn v0_to_v1(_doc: &mut DocumentMut) {}
+
+/// `whitelist` replaced the built-in defaults as a whole,
+/// it's converted to `whitelist_extra` and `whitelist_remove` edits on top of them
+fn v1_to_v2(doc: &mut DocumentMut) {
+    let Some(Item::Value(Value::Array(whitelist))) = doc.remove("whitelist") else {
+        return;
+    };
+
+    let names = whitelist
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>();
+
+    // keep entries as they are, including comments around them
+    let mut extra = whitelist
+        .iter()
+        .filter(|entry| {
+            entry
+                .as_str()
+                .is_some_and(|name| !V1_WHITELIST.contains(&name))
+        })
+        .cloned()
+        .collect::<Array>();
+    extra.set_trailing(whitelist.trailing().clone());
+    extra.set_trailing_comma(whitelist.trailing_comma());
+
+    let mut remove = V1_WHITELIST
+        .into_iter()
+        .filter(|name| !names.contains(name))
+        .collect::<Array>();
+    remove.fmt();
+
+    let mut extra_key = Key::new("whitelist_extra");
+    extra_key
+        .leaf_decor_mut()
+        .set_prefix("# processes never throttled, in addition to the built-in whitelist\n");
+    let mut remove_key = Key::new("whitelist_remove");
+    remove_key
+        .leaf_decor_mut()
+        .set_prefix("# built-in whitelist entries to throttle anyway\n");
+
+    let root = doc.as_table_mut();
+    root.insert_formatted(&extra_key, value(extra));
+    root.insert_formatted(&remove_key, value(remove));
+}
+
+#[cfg(test)]
+mod tests {
+    use toml_edit::DocumentMut;
+
+    use super::{CURRENT_VERSION, MIGRATIONS, V1_WHITELIST, migrate, set_version};
+    use crate::config::DEFAULT_WHITELIST;
+
+    /// apply a single step, upgrading `before` from version `from`
+    fn assert_step(from: u32, before: &str, after: &str) {
+        let mut doc = before.parse::<DocumentMut>().unwrap();
+        MIGRATIONS[from as usize](&mut doc);
+        set_version(doc.as_table_mut(), from + 1);
+        assert_eq!(doc.to_string(), after);
+    }
+
+    #[test]
+    fn v0_to_v1() {
+        let before = r#"# keep my comment
+throttle_all_startup = false
+whitelist = ["foo.exe"] # trailing
+
+[listen_new_process]
+mode = "blacklist_only"
+"#;
+        let after = r#"# version of config layout, managed by RustyStar
+config_version = 1
+# keep my comment
+throttle_all_startup = false
+whitelist = ["foo.exe"] # trailing
+
+[listen_new_process]
+mode = "blacklist_only"
+"#;
+        assert_step(0, before, after);
+    }
+
+    #[test]
+    fn v1_to_v2() {
+        let before = format!(
+            r#"config_version = 1
+throttle_all_startup = false
+whitelist = [
+    # my favorite game
+    "game.exe",
+{}]
+
+[listen_new_process]
+enabled = false
+"#,
+            V1_WHITELIST
+                .iter()
+                .filter(|name| **name != "msedge.exe")
+                .map(|name| format!("    {name:?},\n"))
+                .collect::<String>()
+        );
+        let after = r#"config_version = 2
+throttle_all_startup = false
+# processes never throttled, in addition to the built-in whitelist
+whitelist_extra = [
+    # my favorite game
+    "game.exe",
+]
+# built-in whitelist entries to throttle anyway
+whitelist_remove = ["msedge.exe"]
+
+[listen_new_process]
+enabled = false
+"#;
+        assert_step(1, &before, after);
+    }
+
+    #[test]
+    fn v1_to_v2_without_whitelist() {
+        assert_step(1, "config_version = 1\n", "config_version = 2\n");
+    }
+
+    #[test]
+    fn defaults_started_from_v1() {
+        assert!(
+            V1_WHITELIST
+                .iter()
+                .all(|name| DEFAULT_WHITELIST.contains(name))
+        );
+    }
+
+    #[test]
+    fn chain_reaches_current_version() {
+        let (from, migrated) = migrate("whitelist = []\n").unwrap().unwrap();
+        assert_eq!(from, 0);
+        assert!(migrated.starts_with(&format!(
+            "# version of config layout, managed by RustyStar\nconfig_version = {CURRENT_VERSION}\n"
+        )));
+        assert_eq!(migrate(&migrated).unwrap(), None);
+    }
+
+    #[test]
+    fn up_to_date_is_untouched() {
+        let content = format!("config_version = {CURRENT_VERSION}\n");
+        assert_eq!(migrate(&content).unwrap(), None);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let content = format!("config_version = {}\n", CURRENT_VERSION + 1);
+        assert!(migrate(&content).is_err());
+        assert!(migrate("config_version = -1\n").is_err());
+    }
+}
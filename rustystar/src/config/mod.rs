@@ -1,51 +1,259 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::LazyLock;
+use std::time::Duration;
 
 use compio::fs::{self, File};
 use compio::io::AsyncReadAtExt;
 use directories::ProjectDirs;
-use documented::DocumentedFields;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use spdlog::warn;
+use spdlog::{info, warn};
 
-use crate::config::merge::append_comments;
+use crate::config::layer::Sources;
+use crate::config::merge::{default_document, fill_missing};
+use crate::config::migrate::{CURRENT_VERSION, migrate};
+use crate::config::validate::{Report, Severity};
 
+pub mod layer;
 mod merge;
+pub mod migrate;
+pub mod schema;
+pub mod validate;
+pub mod watch;
 
-#[derive(Debug, Serialize, Deserialize, DocumentedFields)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct ListenForegroundEvents {
     /// listen foreground window change events
     pub enabled: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ListenNewProcessMode {
+    /// throttle all new process
     #[default]
     Normal,
+    /// only throttle blacklisted
     BlacklistOnly,
 }
 
-#[derive(Debug, Serialize, Deserialize, DocumentedFields)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct ListenNewProcess {
     /// listen new process creation
     pub enabled: bool,
-    /// blacklist_only: only throttle blacklisted
-    /// normal: throttle all new process
+    /// which new processes are throttled
     pub mode: ListenNewProcessMode,
     /// blacklist
     pub blacklist: Vec<String>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Metrics {
+    /// serve Prometheus metrics at `http://127.0.0.1:<port>/metrics`
+    pub enabled: bool,
+    /// port on the loopback interface, changes take effect after restart
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DecisionLog {
+    /// append every decision as a JSON line to `decisions.jsonl`, next to `rustystar.log`
+    pub enabled: bool,
+    /// rotate once the file grows over this size, in MiB, `0` never rotates
+    pub max_size_mb: u64,
+    /// rotated files to keep, `decisions.jsonl.1` is the newest
+    pub keep: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Logging {
+    /// least severe level printed to console, `info` if unset, `debug` in debug builds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "LogLevel")]
+    pub console_level: Option<LogLevel>,
+    /// least severe level written to `rustystar.log`
+    pub file_level: LogLevel,
+    /// levels of single modules, for both console and file, e.g. `["events=trace"]`
+    #[schemars(with = "Vec<String>")]
+    pub modules: Vec<ModuleLevel>,
+    /// rotate `rustystar.log` once it grows over this size, in MiB, `0` never rotates by size
+    pub max_size_mb: u64,
+    /// also rotate `rustystar.log` once it's older
+    pub rotate: LogRotation,
+    /// rotated logs to keep, `rustystar.log.1` is the newest
+    pub keep: u32,
+    /// gzip rotated logs to `rustystar.log.1.gz`, ...
+    pub compress: bool,
+}
+
+/// log levels, from least to most verbose
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Default, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    /// nothing
+    Off,
+    /// only failures RustyStar can't recover from
+    Critical,
+    /// and failed operations
+    Error,
+    /// and unexpected conditions
+    Warn,
+    /// and what RustyStar is doing
+    #[default]
+    Info,
+    /// and every decision
+    Debug,
+    /// everything
+    Trace,
+}
+
+/// level override of a module, written as `module=level`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ModuleLevel {
+    /// module path without the crate name, e.g. `events` or `ipc::pipe`
+    pub module: String,
+    pub level: LogLevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRotation {
+    /// only by size
+    #[default]
+    Never,
+    /// once the log is a day old
+    Daily,
+    /// once the log is a week old
+    Weekly,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "off" => LogLevel::Off,
+            "critical" => LogLevel::Critical,
+            "error" => LogLevel::Error,
+            "warn" => LogLevel::Warn,
+            "info" => LogLevel::Info,
+            "debug" => LogLevel::Debug,
+            "trace" => LogLevel::Trace,
+            _ => {
+                return Err(format!(
+                    "unknown log level `{s}`, expected off, critical, error, warn, info, debug or trace"
+                ));
+            }
+        })
+    }
+}
+
+impl TryFrom<String> for ModuleLevel {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (module, level) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected `module=level`, found `{value}`"))?;
+        Ok(Self {
+            module: module.trim().to_string(),
+            level: level.trim().parse()?,
+        })
+    }
+}
+
+impl From<ModuleLevel> for String {
+    fn from(ModuleLevel { module, level }: ModuleLevel) -> Self {
+        format!("{module}={level}")
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Off => "off",
+            LogLevel::Critical => "critical",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        })
+    }
+}
+
+impl LogRotation {
+    pub fn max_age(self) -> Option<Duration> {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        match self {
+            LogRotation::Never => None,
+            LogRotation::Daily => Some(DAY),
+            LogRotation::Weekly => Some(DAY * 7),
+        }
+    }
+}
+
 pub static PROJECT_DIR: LazyLock<Option<ProjectDirs>> =
     LazyLock::new(|| directories::ProjectDirs::from("io", "RustyStarX", "RustyStar"));
 
-#[derive(Debug, Serialize, Deserialize, DocumentedFields)]
+/// marker files next to the executable enabling portable mode
+///
+/// `portable.txt` is accepted since Explorer hides extensions by default.
+pub const PORTABLE_MARKERS: [&str; 2] = ["portable", "portable.txt"];
+
+/// directory of the executable, if RustyStar runs in portable mode
+pub static PORTABLE_DIR: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
+    let exe_dir = std::env::current_exe().ok()?.parent()?.to_path_buf();
+    PORTABLE_MARKERS
+        .iter()
+        .any(|marker| exe_dir.join(marker).is_file())
+        .then_some(exe_dir)
+});
+
+/// directory of `config.toml`, next to the executable in portable mode
+pub fn config_dir() -> PathBuf {
+    PORTABLE_DIR
+        .clone()
+        .or_else(|| PROJECT_DIR.as_ref().map(|d| d.config_dir().to_path_buf()))
+        .unwrap_or(PathBuf::from("."))
+}
+
+/// directory of logs, next to the executable in portable mode
+pub fn data_dir() -> PathBuf {
+    PORTABLE_DIR
+        .clone()
+        .or_else(|| PROJECT_DIR.as_ref().map(|d| d.data_dir().to_path_buf()))
+        .unwrap_or(PathBuf::from("."))
+}
+
+pub fn log_file() -> PathBuf {
+    data_dir().join("rustystar.log")
+}
+
+pub fn decision_log_file() -> PathBuf {
+    data_dir().join("decisions.jsonl")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Config {
+    /// version of config layout, managed by RustyStar
+    pub config_version: u32,
+    /// other config files merged before this one, relative to it
+    ///
+    /// lists of included files are joined, other keys are overridden by later files
+    pub include: Vec<String>,
     /// setup auto-start (enable/disable)
     ///
     /// Only taking effect if `auto-launch` feature enabled
@@ -58,97 +266,264 @@ pub struct Config {
     pub throttle_all_startup: bool,
     /// also taking effect on some `SYSTEM` priviledged process
     pub system_process: bool,
-    /// whitelisted process will not be throttled
-    pub whitelist: Vec<String>,
+    /// observe only, decisions are logged but processes are never changed
+    pub dry_run: bool,
+    /// recent decisions kept in memory, for `RustyStar history` and the control API
+    pub decision_history: usize,
+    /// processes never throttled, in addition to the built-in whitelist
+    ///
+    /// the effective whitelist is logged on startup
+    pub whitelist_extra: Vec<String>,
+    /// built-in whitelist entries to throttle anyway
+    pub whitelist_remove: Vec<String>,
+    /// metrics exporter, only reachable from this machine
+    pub metrics: Metrics,
+    /// machine-readable log of every decision, rotated separately from `rustystar.log`
+    pub decision_log: DecisionLog,
+    /// console and `rustystar.log`, applied live on reload
+    pub logging: Logging,
 }
 
+/// built-in whitelist, processes here will not be throttled
+pub const DEFAULT_WHITELIST: &[&str] = &[
+    // ourself
+    "RustyStar.exe",
+    // System processes
+    "explorer.exe",
+    // Windows Manager of Windows
+    "dwm.exe",
+    // CSRSS core process
+    "csrss.exe",
+    // Windows services process
+    "svchost.exe",
+    // Task Manager
+    "Taskmgr.exe",
+    // Session Manager Subsystem
+    "smss.exe",
+    // Chinese input method
+    "ChsIME.exe",
+    // Speech-To-Text, Screen keyboard, handwrite input, e.g.
+    "ctfmon.exe",
+    // Windows User Mode Driver Framework
+    "WUDFRd.exe",
+    "WUDFHost.exe",
+    // Edge is energy aware
+    "msedge.exe",
+    // UWP special handle
+    "ApplicationFrameHost.exe",
+    // system itself
+    "[System Process]",
+    "System",
+    "Registry",
+    // parent of "services.exe"
+    "wininit.exe",
+    // parent of "svchost.exe", "wudfhost.exe", e.g.
+    "services.exe",
+    // Local Security Authority Subsystem Service
+    "lsass.exe",
+    // part of the Windows Security Center,
+    // responsible for monitoring and reporting the security status of your system
+    "SecurityHealthService.exe",
+];
+
 impl Config {
     pub async fn config_path() -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
-        let config_dir = PROJECT_DIR
-            .as_ref()
-            .map(|d| d.config_dir().to_path_buf())
-            .unwrap_or(PathBuf::from("."));
+        let config_dir = config_dir();
         fs::create_dir_all(&config_dir).await?;
 
         Ok(config_dir.join("config.toml"))
     }
 
-    pub async fn from_profile() -> Result<Self, Box<dyn Error + Send + Sync>> {
-        let config_path = Self::config_path().await?;
-        let config = if config_path.exists() {
-            let file = File::open(&config_path).await?;
-            let result = file.read_to_end_at(Vec::with_capacity(4096), 0).await;
-            if !result.is_ok() {
-                Err("Failed to read configuration")?;
-            }
-            toml::from_str(&String::from_utf8_lossy(&result.1))?
+    /// machine-wide policy, managed by administrators
+    pub fn policy_path() -> PathBuf {
+        std::env::var_os("ProgramData")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+            .join("RustyStar")
+            .join("policy.toml")
+    }
+
+    /// write defaults for new users, migrate outdated config and insert missing keys
+    ///
+    /// files which are up to date are never rewritten.
+    pub async fn prepare_profile(config_path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let updated = if config_path.exists() {
+            let mut content = read_config(config_path).await?;
+            let migrated = if let Some((version, migrated)) = migrate(&content)? {
+                let backup = config_path.with_extension(format!("v{version}.toml.bak"));
+                info!("backing up config before migration to {backup:?}");
+                fs::write(backup, content).await.0?;
+                content = migrated;
+                true
+            } else {
+                false
+            };
+
+            fill_missing(&content)?.or(migrated.then_some(content))
         } else {
-            warn!("config not existing! falling back to default...");
-            Self::default()
+            warn!("config not existing! writing default...");
+            Some(default_document()?)
         };
 
-        let serialized = append_comments(&toml::to_string_pretty(&config)?)?;
-        _ = fs::write(config_path, serialized.trim_start().to_string())
-            .await
-            .0
-            .inspect_err(|e| {
-                warn!("failed to write default config: {e}");
+        if let Some(updated) = updated {
+            info!("updating {config_path:?}");
+            _ = fs::write(config_path, updated).await.0.inspect_err(|e| {
+                warn!("failed to write config: {e}");
             });
+        }
+
+        Ok(())
+    }
+
+    /// built-in whitelist with user's additions and removals applied
+    pub fn whitelist(&self) -> Vec<String> {
+        let builtin = DEFAULT_WHITELIST
+            .iter()
+            .filter(|name| !self.whitelist_remove.iter().any(|removed| removed == *name))
+            .map(|name| name.to_string());
+        let extra = self
+            .whitelist_extra
+            .iter()
+            .filter(|name| !DEFAULT_WHITELIST.contains(&name.as_str()))
+            .cloned();
+
+        builtin.chain(extra).collect()
+    }
+}
+
+/// validate config files and overrides, diagnostics are printed to stdout
+///
+/// returns `false` if any error was found
+pub async fn check_profile(
+    sources: &Sources,
+    json: bool,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let mut valid = true;
+    let mut files = vec![];
+
+    for config_path in sources.files() {
+        if !config_path.exists() {
+            if !json {
+                println!("{}: not existing, skipped", config_path.display());
+            }
+            continue;
+        }
+
+        let content = read_config(&config_path).await?;
+        // the policy is a fragment, it's never migrated
+        let migrated = if config_path == sources.user {
+            migrate(&content)
+        } else {
+            Ok(None)
+        };
+        let content = match migrated {
+            Ok(Some((_, migrated))) => migrated,
+            Ok(None) => content,
+            Err(e) => {
+                if json {
+                    files.push(serde_json::json!({ "path": config_path, "error": e.to_string() }));
+                } else {
+                    println!("{}: error: {e}", config_path.display());
+                }
+                valid = false;
+                continue;
+            }
+        };
+        let report = Report::new(&config_path, &content);
+
+        let errors = report
+            .diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        let warnings = report.diagnostics.len() - errors;
+        valid &= errors == 0;
+
+        if json {
+            let diagnostics = report
+                .diagnostics
+                .iter()
+                .map(|diagnostic| {
+                    let (line, column) = report.location(diagnostic).unzip();
+                    serde_json::json!({
+                        "severity": diagnostic.severity,
+                        "message": diagnostic.message,
+                        "line": line,
+                        "column": column,
+                    })
+                })
+                .collect::<Vec<_>>();
+            files.push(serde_json::json!({ "path": config_path, "diagnostics": diagnostics }));
+        } else {
+            print!("{report}");
+            println!(
+                "{}: {errors} error(s), {warnings} warning(s)",
+                config_path.display()
+            );
+        }
+    }
+
+    // environment variables, `--set` overrides and included files
+    let mut load_error = None;
+    if valid && let Err(e) = sources.load().await {
+        if !json {
+            println!("error: {e}");
+        }
+        load_error = Some(e.to_string());
+        valid = false;
+    }
 
-        Ok(config)
+    if json {
+        let output = serde_json::json!({ "valid": valid, "files": files, "error": load_error });
+        println!("{output:#}");
     }
+
+    Ok(valid)
+}
+
+async fn read_config(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let file = File::open(path).await?;
+    let result = file.read_to_end_at(Vec::with_capacity(4096), 0).await;
+    if !result.is_ok() {
+        Err("Failed to read configuration")?;
+    }
+
+    Ok(String::from_utf8_lossy(&result.1).into_owned())
+}
+
+/// compare `generated` with the checked-in `file`, `UPDATE_SNAPSHOTS=1` refreshes it instead
+#[cfg(test)]
+fn assert_snapshot(file: &Path, generated: &str) {
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(file, generated).unwrap();
+        return;
+    }
+
+    let checked_in = std::fs::read_to_string(file).unwrap_or_default();
+    assert!(
+        checked_in.replace("\r\n", "\n") == generated,
+        "{} is outdated, run `UPDATE_SNAPSHOTS=1 cargo test` to regenerate it",
+        file.display()
+    );
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_version: CURRENT_VERSION,
+            include: vec![],
             autostart_on_boot: false,
             listen_new_process: ListenNewProcess::default(),
             listen_foreground_events: ListenForegroundEvents::default(),
             throttle_all_startup: true,
             system_process: true,
-            whitelist: [
-                // ourself
-                "RustyStar.exe",
-                // System processes
-                "explorer.exe",
-                // Windows Manager of Windows
-                "dwm.exe",
-                // CSRSS core process
-                "csrss.exe",
-                // Windows services process
-                "svchost.exe",
-                // Task Manager
-                "Taskmgr.exe",
-                // Session Manager Subsystem
-                "smss.exe",
-                // Chinese input method
-                "ChsIME.exe",
-                // Speech-To-Text, Screen keyboard, handwrite input, e.g.
-                "ctfmon.exe",
-                // Windows User Mode Driver Framework
-                "WUDFRd.exe",
-                "WUDFHost.exe",
-                // Edge is energy aware
-                "msedge.exe",
-                // UWP special handle
-                "ApplicationFrameHost.exe",
-                // system itself
-                "[System Process]",
-                "System",
-                "Registry",
-                // parent of "services.exe"
-                "wininit.exe",
-                // parent of "svchost.exe", "wudfhost.exe", e.g.
-                "services.exe",
-                // Local Security Authority Subsystem Service
-                "lsass.exe",
-                // part of the Windows Security Center,
-                // responsible for monitoring and reporting the security status of your system
-                "SecurityHealthService.exe",
-            ]
-            .map(str::to_string)
-            .to_vec(),
+            dry_run: false,
+            decision_history: 1000,
+            whitelist_extra: vec![],
+            whitelist_remove: vec![],
+            metrics: Metrics::default(),
+            decision_log: DecisionLog::default(),
+            logging: Logging::default(),
         }
     }
 }
@@ -159,6 +534,40 @@ impl Default for ListenForegroundEvents {
     }
 }
 
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 9810,
+        }
+    }
+}
+
+impl Default for DecisionLog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_size_mb: 10,
+            keep: 5,
+        }
+    }
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            // resolved by the logging setup, defaults are the same in every build
+            console_level: None,
+            file_level: LogLevel::default(),
+            modules: vec![],
+            max_size_mb: 10,
+            rotate: LogRotation::default(),
+            keep: 5,
+            compress: false,
+        }
+    }
+}
+
 impl Default for ListenNewProcess {
     fn default() -> Self {
         Self {
@@ -0,0 +1,148 @@
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+use schemars::schema_for;
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// where the schema is published, referenced by `#:schema` in `config.toml`
+pub const SCHEMA_URL: &str =
+    "https://raw.githubusercontent.com/RustyStarX/RustyStar/main/docs/config.schema.json";
+
+/// schema of `Config`, the source of docs and known keys
+pub static SCHEMA: LazyLock<Value> = LazyLock::new(|| schema_for!(Config).to_value());
+
+/// JSON schema of `config.toml`, for editor completion and validation
+pub fn json_schema() -> String {
+    let schema = schema_for!(Config);
+    serde_json::to_string_pretty(&schema).expect("schema is always serializable") + "\n"
+}
+
+/// Markdown reference of all options, generated from the JSON schema
+pub fn markdown_reference() -> String {
+    let schema = &*SCHEMA;
+
+    let mut doc = String::from(
+        "# Configuration reference\n\n\
+         <!-- generated from `Config` by `cargo test`, do not edit -->\n\n\
+         Options of `config.toml`, every option is optional and falls back to its default.\n",
+    );
+    write_properties(&mut doc, schema, schema, "");
+    doc
+}
+
+fn write_properties(doc: &mut String, schema: &Value, root: &Value, prefix: &str) {
+    let Some(properties) = schema["properties"].as_object() else {
+        return;
+    };
+
+    // plain values first, tables must come after them in TOML
+    let (tables, values) = properties
+        .iter()
+        .partition::<Vec<_>, _>(|(_, property)| is_table(property, root));
+
+    for (key, property) in values {
+        let heading = if prefix.is_empty() { "##" } else { "###" };
+        _ = writeln!(doc, "\n{heading} `{prefix}{key}`");
+        write_description(doc, property);
+
+        let resolved = resolve(property, root);
+        _ = writeln!(doc, "\n- type: {}", type_name(resolved));
+        if let Some(default) = property.get("default") {
+            _ = writeln!(doc, "- default: `{default}`");
+        }
+        if let Some(variants) = resolved["oneOf"].as_array() {
+            _ = writeln!(doc, "- values:");
+            for variant in variants {
+                _ = write!(doc, "  - `{}`", variant["const"]);
+                match variant["description"].as_str() {
+                    Some(description) => _ = writeln!(doc, ": {description}"),
+                    None => doc.push('\n'),
+                }
+            }
+        }
+    }
+
+    for (key, property) in tables {
+        _ = writeln!(doc, "\n## `[{prefix}{key}]`");
+        write_description(doc, property);
+        write_properties(
+            doc,
+            resolve(property, root),
+            root,
+            &format!("{prefix}{key}."),
+        );
+    }
+}
+
+fn write_description(doc: &mut String, property: &Value) {
+    let Some(description) = property["description"].as_str() else {
+        return;
+    };
+
+    // single line breaks in doc comments are kept as hard breaks
+    doc.push('\n');
+    let mut lines = description.lines().peekable();
+    while let Some(line) = lines.next() {
+        let hard_break = !line.is_empty() && lines.peek().is_some_and(|next| !next.is_empty());
+        _ = writeln!(doc, "{line}{}", if hard_break { "  " } else { "" });
+    }
+}
+
+/// follow `$ref` of `property` to its definition in `root`
+pub fn resolve<'a>(property: &'a Value, root: &'a Value) -> &'a Value {
+    property["$ref"]
+        .as_str()
+        .and_then(|reference| reference.strip_prefix('#'))
+        .and_then(|pointer| root.pointer(pointer))
+        .unwrap_or(property)
+}
+
+/// keys of a table, in declaration order
+pub fn fields<'a>(schema: &'a Value, root: &'a Value) -> Vec<&'a str> {
+    resolve(schema, root)["properties"]
+        .as_object()
+        .map(|properties| properties.keys().map(String::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// schema of `key` in a table
+pub fn property<'a>(schema: &'a Value, key: &str, root: &'a Value) -> Option<&'a Value> {
+    resolve(schema, root)["properties"].get(key)
+}
+
+/// schema of a dotted key, e.g. `listen_new_process.mode`
+pub fn lookup(path: &str) -> Option<&'static Value> {
+    path.split('.')
+        .try_fold(&*SCHEMA, |schema, key| property(schema, key, &SCHEMA))
+}
+
+pub fn is_table(property: &Value, root: &Value) -> bool {
+    resolve(property, root)["type"] == "object"
+}
+
+fn type_name(schema: &Value) -> String {
+    match schema["type"].as_str() {
+        Some("array") => format!("array of {}", type_name(&schema["items"])),
+        Some(name) => name.to_string(),
+        None if schema["oneOf"].is_array() => "string".to_string(),
+        None => "any".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{json_schema, markdown_reference};
+    use crate::config::assert_snapshot;
+
+    #[test]
+    fn docs_are_up_to_date() {
+        let docs = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../docs");
+
+        assert_snapshot(&docs.join("config.schema.json"), &json_schema());
+        assert_snapshot(&docs.join("configuration.md"), &markdown_reference());
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::Path;
+
+use serde::Serialize;
+use toml_edit::{Document, Item, TableLike, Value};
+
+use crate::config::schema::{self, SCHEMA, fields, is_table, lookup};
+use crate::config::{Config, DEFAULT_WHITELIST};
+
+/// process name lists, checked for empty and duplicated entries
+const PROCESS_LISTS: [&str; 3] = [
+    "whitelist_extra",
+    "whitelist_remove",
+    "listen_new_process.blacklist",
+];
+
+/// a process listed in both lists of a pair is ambiguous
+const CONFLICTING_LISTS: [(&str, &str); 2] = [
+    ("whitelist_extra", "whitelist_remove"),
+    ("whitelist_extra", "listen_new_process.blacklist"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// byte range in the document
+    pub span: Option<Range<usize>>,
+}
+
+/// all diagnostics of a config document
+pub struct Report<'a> {
+    path: &'a Path,
+    content: &'a str,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(path: &'a Path, content: &'a str) -> Self {
+        Self {
+            path,
+            content,
+            diagnostics: check(content),
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    /// 1-based line and column of a diagnostic
+    pub fn location(&self, diagnostic: &Diagnostic) -> Option<(usize, usize)> {
+        let span = diagnostic.span.as_ref()?;
+        Some(line_column(self.content, span.start))
+    }
+
+    /// render a single diagnostic, with the offending source line
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let Diagnostic {
+            severity,
+            message,
+            span,
+        } = diagnostic;
+        let severity = match severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let path = self.path.display();
+
+        let Some(span) = span else {
+            return format!("{path}: {severity}: {message}");
+        };
+
+        let (line, column) = line_column(self.content, span.start);
+        let source = self.content.lines().nth(line - 1).unwrap_or_default();
+        let width = self.content[span.clone()]
+            .lines()
+            .next()
+            .map_or(1, |s| s.chars().count().max(1));
+
+        let marker = format!("{:>1$}", "^".repeat(width), column - 1 + width);
+        format!(
+            "{path}:{line}:{column}: {severity}: {message}\n{line:>5} | {source}\n      | {marker}"
+        )
+    }
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{}", self.render(diagnostic))?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    fn error(message: impl Into<String>, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    fn warning(message: impl Into<String>, span: Option<Range<usize>>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// validate a config document, diagnostics are ordered by their position
+pub fn check(content: &str) -> Vec<Diagnostic> {
+    let doc = match Document::parse(content) {
+        Ok(doc) => doc,
+        Err(e) => return vec![Diagnostic::error(e.message().trim(), e.span())],
+    };
+
+    let mut diagnostics = Vec::new();
+    check_keys(doc.as_table(), "", &SCHEMA, &mut diagnostics);
+    check_process_lists(doc.as_item(), &mut diagnostics);
+
+    // unknown keys were reported above, serde will only complain about types
+    if let Err(e) = toml::from_str::<Config>(content) {
+        diagnostics.push(Diagnostic::error(e.message().trim(), e.span()));
+    }
+
+    diagnostics.sort_by_key(|d| d.span.as_ref().map(|span| span.start));
+    diagnostics
+}
+
+/// whether `path` is a valid dotted key, e.g. `listen_new_process.mode`
+pub fn known_key(path: &str) -> bool {
+    lookup(path).is_some()
+}
+
+fn check_keys(
+    table: &dyn TableLike,
+    prefix: &str,
+    schema: &serde_json::Value,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let known = fields(schema, &SCHEMA);
+
+    for (key, item) in table.iter() {
+        let path = format!("{prefix}{key}");
+
+        let Some(property) = schema::property(schema, key, &SCHEMA) else {
+            let span = table.get_key_value(key).and_then(|(k, _)| k.span());
+            // unknown keys are ignored, a typo shouldn't keep RustyStar from starting
+            let diagnostic = match suggest(prefix, key, &known) {
+                // config.toml is migrated before, included files and the policy are not
+                _ if path == "whitelist" => Diagnostic::error(
+                    "legacy key `whitelist`, use `whitelist_extra` and `whitelist_remove` instead",
+                    span,
+                ),
+                Some(suggestion) => Diagnostic::warning(
+                    format!("unknown key `{path}`, did you mean `{suggestion}`?"),
+                    span,
+                ),
+                None => Diagnostic::warning(format!("unknown key `{path}`"), span),
+            };
+            diagnostics.push(diagnostic);
+            continue;
+        };
+
+        let sub_prefix = format!("{path}.");
+        if let Some(sub_table) = item.as_table_like()
+            && is_table(property, &SCHEMA)
+        {
+            check_keys(sub_table, &sub_prefix, property, diagnostics);
+        } else if let Some(array) = item.as_array_of_tables() {
+            let items = &schema::resolve(property, &SCHEMA)["items"];
+            for sub_table in array.iter() {
+                check_keys(sub_table, &sub_prefix, items, diagnostics);
+            }
+        }
+    }
+}
+
+fn suggest(prefix: &str, key: &str, known: &[&str]) -> Option<String> {
+    // e.g. `blacklist` placed at top level
+    if prefix.is_empty() {
+        for parent in fields(&SCHEMA, &SCHEMA) {
+            if lookup(&format!("{parent}.{key}")).is_some() {
+                return Some(format!("{parent}.{key}"));
+            }
+        }
+    }
+
+    // e.g. `whitelist` for `whitelist_extra`
+    let extended = known.iter().find(|candidate| candidate.starts_with(key));
+
+    known
+        .iter()
+        .map(|candidate| (levenshtein(key, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len().div_ceil(3))
+        .min()
+        .map(|(_, candidate)| candidate)
+        .or(extended)
+        .map(|candidate| format!("{prefix}{candidate}"))
+}
+
+fn check_process_lists(root: &Item, diagnostics: &mut Vec<Diagnostic>) {
+    let mut lists = HashMap::<&str, Vec<&Value>>::new();
+
+    for list_key in PROCESS_LISTS {
+        let Some(array) = list_key
+            .split('.')
+            .try_fold(root, |item, key| item.get(key))
+            .and_then(Item::as_array)
+        else {
+            continue;
+        };
+
+        let mut seen = Vec::<&str>::new();
+        for value in array.iter() {
+            // type errors are reported by serde
+            let Some(name) = value.as_str() else {
+                continue;
+            };
+
+            if name.trim().is_empty() {
+                diagnostics.push(Diagnostic::error(
+                    format!("empty process name in `{list_key}`"),
+                    value.span(),
+                ));
+                continue;
+            }
+
+            if seen.contains(&name) {
+                diagnostics.push(Diagnostic::warning(
+                    format!("`{name}` is listed more than once in `{list_key}`"),
+                    value.span(),
+                ));
+            } else if let Some(other) = seen.iter().find(|other| other.eq_ignore_ascii_case(name)) {
+                diagnostics.push(Diagnostic::warning(
+                    format!("`{name}` differs from `{other}` only in case, process names are case sensitive"),
+                    value.span(),
+                ));
+            }
+            seen.push(name);
+
+            if list_key == "whitelist_remove" && !DEFAULT_WHITELIST.contains(&name) {
+                diagnostics.push(Diagnostic::warning(
+                    format!("`{name}` is not in the built-in whitelist, removing it has no effect"),
+                    value.span(),
+                ));
+            }
+
+            lists.entry(list_key).or_default().push(value);
+        }
+    }
+
+    for (first, second) in CONFLICTING_LISTS {
+        let (Some(first_list), Some(second_list)) = (lists.get(first), lists.get(second)) else {
+            continue;
+        };
+
+        for value in second_list {
+            if first_list
+                .iter()
+                .any(|other| other.as_str() == value.as_str())
+            {
+                diagnostics.push(Diagnostic::warning(
+                    format!(
+                        "`{}` is listed in both `{first}` and `{second}`",
+                        value.as_str().unwrap_or_default()
+                    ),
+                    value.span(),
+                ));
+            }
+        }
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Severity, check, levenshtein, line_column};
+
+    fn messages(content: &str) -> Vec<(Severity, String)> {
+        check(content)
+            .into_iter()
+            .map(|d| (d.severity, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn default_config_is_clean() {
+        let content = crate::config::merge::default_document().unwrap();
+        assert!(check(&content).is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_suggested() {
+        let content = "whitelst_extra = []\nblacklist = []\nwhitelist = []\n[listen_new_process]\nenable = true\n";
+        assert_eq!(
+            messages(content),
+            [
+                (
+                    Severity::Warning,
+                    "unknown key `whitelst_extra`, did you mean `whitelist_extra`?".into()
+                ),
+                (
+                    Severity::Warning,
+                    "unknown key `blacklist`, did you mean `listen_new_process.blacklist`?".into()
+                ),
+                (
+                    Severity::Error,
+                    "legacy key `whitelist`, use `whitelist_extra` and `whitelist_remove` instead"
+                        .into()
+                ),
+                (
+                    Severity::Warning,
+                    "unknown key `listen_new_process.enable`, did you mean `listen_new_process.enabled`?"
+                        .into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicated_and_conflicting_entries() {
+        let content = r#"
+whitelist_extra = ["a.exe", "b.exe", "a.exe", "B.exe", ""]
+whitelist_remove = ["dwm.exe", "c.exe", "a.exe"]
+[listen_new_process]
+blacklist = ["b.exe"]
+"#;
+        let diagnostics = check(content);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (
+                    d.severity,
+                    line_column(content, d.span.clone().unwrap().start)
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (Severity::Warning, (2, 38)),
+                (Severity::Warning, (2, 47)),
+                (Severity::Error, (2, 56)),
+                (Severity::Warning, (3, 32)),
+                (Severity::Warning, (3, 41)),
+                (Severity::Warning, (3, 41)),
+                (Severity::Warning, (5, 14)),
+            ]
+        );
+        assert!(diagnostics[3].message.contains("no effect"));
+        assert!(
+            diagnostics[6]
+                .message
+                .contains("both `whitelist_extra` and")
+        );
+    }
+
+    #[test]
+    fn type_errors_have_spans() {
+        let content = "throttle_all_startup = \"yes\"\n";
+        let diagnostics = check(content);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(
+            line_column(content, diagnostics[0].span.clone().unwrap().start),
+            (1, 24)
+        );
+    }
+
+    #[test]
+    fn module_levels_are_checked() {
+        let content = "[logging]\nmodules = [\"events=trace\", \"ipc=loud\"]\n";
+        let diagnostics = check(content);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("unknown log level `loud`"));
+        assert_eq!(
+            line_column(content, diagnostics[0].span.clone().unwrap().start),
+            (2, 11)
+        );
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("whitelst", "whitelist"), 1);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+    }
+}
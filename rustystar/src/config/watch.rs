@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use compio::time::sleep;
+
+/// how often config files are checked for modification
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// editors may truncate and write in several steps, wait for them to finish
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// set by [`request_reload`], picked up on the next poll
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// reload config files even if they weren't modified
+pub fn request_reload() {
+    RELOAD_REQUESTED.store(true, Ordering::Release);
+}
+
+pub struct ConfigWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified_time(&path);
+                (path, modified)
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// replace watched files, e.g. after `include` changed
+    pub fn watch(&mut self, paths: Vec<PathBuf>) {
+        if self.files.iter().map(|(path, _)| path).eq(&paths) {
+            return;
+        }
+        *self = Self::new(paths);
+    }
+
+    /// wait until any of config files was created, modified or removed, or a reload was requested
+    pub async fn changed(&mut self) {
+        loop {
+            sleep(POLL_INTERVAL).await;
+
+            if RELOAD_REQUESTED.swap(false, Ordering::AcqRel) {
+                return;
+            }
+
+            let mut modified = self.modified_times();
+            if !self
+                .files
+                .iter()
+                .zip(&modified)
+                .any(|((_, last), now)| last != now)
+            {
+                continue;
+            }
+
+            loop {
+                sleep(SETTLE_DELAY).await;
+                let settled = self.modified_times();
+                if settled == modified {
+                    break;
+                }
+                modified = settled;
+            }
+
+            for ((_, last), now) in self.files.iter_mut().zip(modified) {
+                *last = now;
+            }
+            return;
+        }
+    }
+
+    fn modified_times(&self) -> Vec<Option<SystemTime>> {
+        self.files
+            .iter()
+            .map(|(path, _)| modified_time(path))
+            .collect()
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
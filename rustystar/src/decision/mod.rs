@@ -0,0 +1,709 @@
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize, Serializer};
+use spdlog::{debug, info};
+use win32_ecoqos::process::toggle_efficiency_mode;
+use win32_ecoqos::utils::Process;
+use win32_ecoqos::windows_result;
+
+use crate::bus::{ErrorClass, Event, publish};
+use crate::config::ListenNewProcessMode;
+use crate::logging::write_decision;
+use crate::metrics::METRICS;
+use crate::stats;
+
+/// observe only, decisions are logged instead of applied
+pub static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// throttling is paused, decisions are made but not applied, see [`crate::pause`]
+pub static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// processes whose EcoQoS state was changed, by pid
+static TOUCHED: LazyLock<Mutex<AHashMap<u32, Touched>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone)]
+pub struct Touched {
+    pub name: OsString,
+    /// last action applied, never [`Action::Restore`]
+    pub action: Action,
+}
+
+/// how many decisions are kept for [`history`], set by config
+static HISTORY_CAPACITY: AtomicUsize = AtomicUsize::new(1000);
+
+static HISTORY: LazyLock<Mutex<VecDeque<Record>>> = LazyLock::new(Default::default);
+
+/// a decision carried out by [`execute`]
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    /// milliseconds since unix epoch
+    pub time: u64,
+    pub pid: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<u32>,
+    pub name: String,
+    pub trigger: Trigger,
+    /// `None` if the process was skipped
+    pub action: Option<Action>,
+    /// check which decided, `default` if none did
+    pub reason: &'static str,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// microseconds from the event to the decision being carried out
+    pub latency_us: u64,
+}
+
+/// filter of [`history`], every given condition must match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Query {
+    pub pid: Option<u32>,
+    /// executable name, case-insensitive
+    pub name: Option<String>,
+    /// milliseconds since unix epoch, inclusive
+    pub since: Option<u64>,
+    /// milliseconds since unix epoch, exclusive
+    pub until: Option<u64>,
+    /// only the newest ones
+    pub limit: Option<usize>,
+}
+
+/// what came of a decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Applied,
+    Skipped,
+    /// held back by dry-run mode
+    DryRun,
+    /// held back by a pause
+    Paused,
+    Failed,
+}
+
+/// the process a decision is about
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Subject<'a> {
+    pub pid: u32,
+    /// `None` if unknown
+    pub parent: Option<u32>,
+    pub name: &'a OsStr,
+}
+
+impl<'a> From<&'a Process> for Subject<'a> {
+    fn from(process: &'a Process) -> Self {
+        Self {
+            pid: process.process_id,
+            parent: Some(process.process_parent_id),
+            name: &process.process_name,
+        }
+    }
+}
+
+/// what RustyStar does to a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// enable EcoQoS
+    Throttle,
+    /// disable EcoQoS
+    Boost,
+    /// let Windows decide again
+    Restore,
+}
+
+/// why a process was left as it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skip {
+    Whitelisted,
+    ForegroundChild,
+    Fullscreen,
+    NotBlacklisted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Apply(Action),
+    Skip(Skip),
+}
+
+/// event a decision is made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// the process was just started
+    Started,
+    /// the process, or the tree it belongs to, came to foreground
+    Foreground,
+    /// the process, or the tree it belongs to, left foreground
+    Background,
+    /// `action` is applied to all processes, e.g. on startup and exit
+    Sweep(Action),
+    /// `action` was requested over the control API
+    Requested(Action),
+    /// throttling was paused, processes RustyStar changed are restored
+    Paused,
+}
+
+/// what is known about a process when deciding
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Facts {
+    pub whitelisted: bool,
+    pub blacklisted: bool,
+    /// in the process tree of the current foreground process
+    pub in_foreground_tree: bool,
+    /// a fullscreen app is running
+    pub fullscreen: bool,
+    pub mode: ListenNewProcessMode,
+}
+
+/// a check evaluated on the way to a decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Step {
+    pub check: &'static str,
+    pub matched: bool,
+}
+
+/// checks evaluated in order, and the decision they lead to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trace {
+    pub trigger: Trigger,
+    pub steps: Vec<Step>,
+    /// check which decided, `None` if the default action was taken
+    pub rule: Option<&'static str>,
+    pub decision: Decision,
+}
+
+/// steps recorded by [`decide`]
+#[derive(Default)]
+struct Checks(Vec<Step>);
+
+impl Checks {
+    /// record `check`, whether it `matched`
+    fn check(&mut self, check: &'static str, matched: bool) -> bool {
+        self.0.push(Step { check, matched });
+        matched
+    }
+
+    /// the last matched check decided
+    fn decided(self, trigger: Trigger, decision: Decision) -> Trace {
+        Trace {
+            trigger,
+            rule: self
+                .0
+                .iter()
+                .rfind(|step| step.matched)
+                .map(|step| step.check),
+            steps: self.0,
+            decision,
+        }
+    }
+
+    /// no check decided, `decision` is the default
+    fn fallback(self, trigger: Trigger, decision: Decision) -> Trace {
+        Trace {
+            trigger,
+            steps: self.0,
+            rule: None,
+            decision,
+        }
+    }
+}
+
+/// decide what to do with a process on `trigger`
+pub fn decide(trigger: Trigger, facts: &Facts) -> Trace {
+    let mut checks = Checks::default();
+
+    match trigger {
+        Trigger::Started => {
+            if checks.check(
+                "blacklist only",
+                facts.mode == ListenNewProcessMode::BlacklistOnly,
+            ) {
+                if checks.check("blacklisted", facts.blacklisted) {
+                    checks.decided(trigger, Decision::Apply(Action::Throttle))
+                } else {
+                    checks.decided(trigger, Decision::Skip(Skip::NotBlacklisted))
+                }
+            } else if checks.check("whitelisted", facts.whitelisted) {
+                checks.decided(trigger, Decision::Skip(Skip::Whitelisted))
+            } else if checks.check("in foreground tree", facts.in_foreground_tree) {
+                checks.decided(trigger, Decision::Skip(Skip::ForegroundChild))
+            } else {
+                checks.fallback(trigger, Decision::Apply(Action::Throttle))
+            }
+        }
+        Trigger::Foreground => {
+            if checks.check("whitelisted", facts.whitelisted) {
+                checks.decided(trigger, Decision::Skip(Skip::Whitelisted))
+            } else {
+                checks.fallback(trigger, Decision::Apply(Action::Boost))
+            }
+        }
+        Trigger::Background => {
+            if checks.check("fullscreen", facts.fullscreen) {
+                checks.decided(trigger, Decision::Skip(Skip::Fullscreen))
+            } else if checks.check("whitelisted", facts.whitelisted) {
+                checks.decided(trigger, Decision::Skip(Skip::Whitelisted))
+            } else {
+                checks.fallback(trigger, Decision::Apply(Action::Throttle))
+            }
+        }
+        Trigger::Sweep(action) | Trigger::Requested(action) => {
+            if checks.check("whitelisted", facts.whitelisted) {
+                checks.decided(trigger, Decision::Skip(Skip::Whitelisted))
+            } else {
+                checks.fallback(trigger, Decision::Apply(action))
+            }
+        }
+        Trigger::Paused => checks.fallback(trigger, Decision::Apply(Action::Restore)),
+    }
+}
+
+impl Action {
+    /// `enable` as taken by [`toggle_efficiency_mode`]
+    pub fn from_enable(enable: Option<bool>) -> Self {
+        match enable {
+            Some(true) => Action::Throttle,
+            Some(false) => Action::Boost,
+            None => Action::Restore,
+        }
+    }
+
+    pub fn enable(self) -> Option<bool> {
+        match self {
+            Action::Throttle => Some(true),
+            Action::Boost => Some(false),
+            Action::Restore => None,
+        }
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decision = match self {
+            Decision::Apply(Action::Throttle) => "throttle",
+            Decision::Apply(Action::Boost) => "boost",
+            Decision::Apply(Action::Restore) => "restore",
+            Decision::Skip(Skip::Whitelisted) => "skip, whitelisted",
+            Decision::Skip(Skip::ForegroundChild) => "skip, foreground child",
+            Decision::Skip(Skip::Fullscreen) => "skip, fullscreen",
+            Decision::Skip(Skip::NotBlacklisted) => "skip, not blacklisted",
+        };
+        f.pad(decision)
+    }
+}
+
+impl Serialize for Decision {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Started => write!(f, "when started"),
+            Trigger::Foreground => write!(f, "when in foreground"),
+            Trigger::Background => write!(f, "when left foreground"),
+            Trigger::Sweep(Action::Throttle) => write!(f, "when throttling all"),
+            Trigger::Sweep(Action::Boost) => write!(f, "when boosting all"),
+            Trigger::Sweep(Action::Restore) => write!(f, "when restoring all"),
+            Trigger::Requested(_) => write!(f, "when requested"),
+            Trigger::Paused => write!(f, "when paused"),
+        }
+    }
+}
+
+/// carry out the decision of `trace` on an event `received` before
+///
+/// In dry-run mode it's only logged, while paused only restoring is carried out.
+pub fn execute(
+    subject: Subject<'_>,
+    trace: &Trace,
+    received: Instant,
+) -> windows_result::Result<Outcome> {
+    let Subject { pid, parent, name } = subject;
+    let decision = trace.decision;
+
+    let dry_run = DRY_RUN.load(Ordering::Acquire);
+    let outcome = hold_back(decision, dry_run, PAUSED.load(Ordering::Acquire));
+    match outcome {
+        _ if dry_run => info!("[dry-run] [{decision:^22}] process {pid:6}: {name:?}"),
+        Outcome::Skipped => debug!("[{decision:^22}] process {pid:6}: {name:?}"),
+        Outcome::Paused => debug!("[paused] [{decision:^22}] process {pid:6}: {name:?}"),
+        _ => {}
+    }
+
+    let result = match (outcome, decision) {
+        (Outcome::Applied, Decision::Apply(action)) => toggle_efficiency_mode(pid, action.enable()),
+        _ => Ok(()),
+    };
+
+    if let (Outcome::Applied, Decision::Apply(action)) = (outcome, decision) {
+        if result.is_ok() {
+            touch(pid, name, action);
+        }
+        let access_denied = result
+            .as_ref()
+            .is_err_and(|e| ErrorClass::of(e.code().0) == ErrorClass::AccessDenied);
+        METRICS.applied(action, result.is_ok(), access_denied);
+        let name = name.to_string_lossy().into_owned();
+        match &result {
+            Ok(()) => publish(Event::applied(pid, name, action)),
+            Err(e) => publish(Event::Failed {
+                pid,
+                name,
+                action,
+                error: ErrorClass::of(e.code().0),
+                message: e.message(),
+            }),
+        }
+    }
+
+    record(Record {
+        time: unix_millis(),
+        pid,
+        parent,
+        name: name.to_string_lossy().into_owned(),
+        trigger: trace.trigger,
+        action: match decision {
+            Decision::Apply(action) => Some(action),
+            Decision::Skip(_) => None,
+        },
+        reason: trace.rule.unwrap_or("default"),
+        outcome: if result.is_err() {
+            Outcome::Failed
+        } else {
+            outcome
+        },
+        error: result.as_ref().err().map(ToString::to_string),
+        latency_us: received.elapsed().as_micros() as u64,
+    });
+    result.map(|()| outcome)
+}
+
+/// what comes of `decision` before it's applied
+fn hold_back(decision: Decision, dry_run: bool, paused: bool) -> Outcome {
+    match decision {
+        Decision::Apply(_) if dry_run => Outcome::DryRun,
+        Decision::Skip(_) => Outcome::Skipped,
+        // pausing stops throttling, processes are still handed back to Windows
+        Decision::Apply(action) if paused && action != Action::Restore => Outcome::Paused,
+        Decision::Apply(_) => Outcome::Applied,
+    }
+}
+
+/// milliseconds since unix epoch
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn record(record: Record) {
+    write_decision(&record);
+    stats::observe(&record);
+
+    let Ok(mut history) = HISTORY.lock() else {
+        return;
+    };
+    let capacity = HISTORY_CAPACITY.load(Ordering::Acquire);
+    while !history.is_empty() && history.len() >= capacity {
+        history.pop_front();
+    }
+    if capacity > 0 {
+        history.push_back(record);
+    }
+}
+
+/// keep at most `capacity` decisions for [`history`], the oldest are dropped
+pub fn set_history_capacity(capacity: usize) {
+    HISTORY_CAPACITY.store(capacity, Ordering::Release);
+    if let Ok(mut history) = HISTORY.lock() {
+        let excess = history.len().saturating_sub(capacity);
+        history.drain(..excess);
+    }
+}
+
+fn touch(pid: u32, name: &OsStr, action: Action) {
+    let Ok(mut touched) = TOUCHED.lock() else {
+        return;
+    };
+    match action {
+        Action::Restore => _ = touched.remove(&pid),
+        Action::Throttle | Action::Boost => {
+            let name = name.to_owned();
+            touched.insert(pid, Touched { name, action });
+        }
+    }
+}
+
+/// processes currently throttled by RustyStar
+pub fn throttled_count() -> usize {
+    TOUCHED.lock().map_or(0, |touched| {
+        touched
+            .values()
+            .filter(|touched| touched.action == Action::Throttle)
+            .count()
+    })
+}
+
+/// processes currently changed by RustyStar, by pid
+pub fn touched() -> Vec<(u32, Touched)> {
+    TOUCHED.lock().map_or_else(
+        |_| vec![],
+        |touched| {
+            touched
+                .iter()
+                .map(|(pid, touched)| (*pid, touched.clone()))
+                .collect()
+        },
+    )
+}
+
+/// processes changed since last call, they are considered untouched afterwards
+pub fn take_touched() -> AHashMap<u32, Touched> {
+    TOUCHED
+        .lock()
+        .map(|mut touched| std::mem::take(&mut *touched))
+        .unwrap_or_default()
+}
+
+/// `pid` exited, it's no longer RustyStar's to restore
+pub fn untouch(pid: u32) {
+    if let Ok(mut touched) = TOUCHED.lock() {
+        touched.remove(&pid);
+    }
+}
+
+/// decisions kept in memory matching `query`, newest last
+pub fn history(query: &Query) -> Vec<Record> {
+    let Ok(history) = HISTORY.lock() else {
+        return vec![];
+    };
+    let mut matched = history
+        .iter()
+        .rev()
+        .filter(|record| query.matches(record))
+        .take(query.limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect::<Vec<_>>();
+    matched.reverse();
+    matched
+}
+
+impl Query {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.pid.is_none_or(|pid| record.pid == pid)
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| record.name.eq_ignore_ascii_case(name))
+            && self.since.is_none_or(|since| record.time >= since)
+            && self.until.is_none_or(|until| record.time < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        Action, Decision, Facts, Outcome, Query, Record, Skip, Trigger, decide, hold_back,
+    };
+    use crate::config::ListenNewProcessMode;
+
+    #[test]
+    fn started_process_is_throttled_by_default() {
+        let trace = decide(Trigger::Started, &Facts::default());
+
+        assert_eq!(trace.decision, Decision::Apply(Action::Throttle));
+        assert_eq!(trace.rule, None);
+        let checks = trace
+            .steps
+            .iter()
+            .map(|step| step.check)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            checks,
+            ["blacklist only", "whitelisted", "in foreground tree"]
+        );
+    }
+
+    #[test]
+    fn first_matching_check_decides() {
+        let facts = Facts {
+            whitelisted: true,
+            in_foreground_tree: true,
+            ..Default::default()
+        };
+        let trace = decide(Trigger::Started, &facts);
+
+        assert_eq!(trace.decision, Decision::Skip(Skip::Whitelisted));
+        assert_eq!(trace.rule, Some("whitelisted"));
+        assert_eq!(trace.steps.len(), 2);
+
+        let facts = Facts {
+            in_foreground_tree: true,
+            ..Default::default()
+        };
+        let trace = decide(Trigger::Started, &facts);
+        assert_eq!(trace.decision, Decision::Skip(Skip::ForegroundChild));
+        assert_eq!(trace.rule, Some("in foreground tree"));
+    }
+
+    #[test]
+    fn blacklist_only_ignores_whitelist() {
+        let mut facts = Facts {
+            whitelisted: true,
+            mode: ListenNewProcessMode::BlacklistOnly,
+            ..Default::default()
+        };
+        let trace = decide(Trigger::Started, &facts);
+        assert_eq!(trace.decision, Decision::Skip(Skip::NotBlacklisted));
+        assert_eq!(trace.rule, Some("blacklist only"));
+
+        facts.blacklisted = true;
+        let trace = decide(Trigger::Started, &facts);
+        assert_eq!(trace.decision, Decision::Apply(Action::Throttle));
+        assert_eq!(trace.rule, Some("blacklisted"));
+    }
+
+    #[test]
+    fn fullscreen_keeps_process_boosted() {
+        let facts = Facts {
+            fullscreen: true,
+            ..Default::default()
+        };
+
+        let trace = decide(Trigger::Background, &facts);
+        assert_eq!(trace.decision, Decision::Skip(Skip::Fullscreen));
+
+        let trace = decide(Trigger::Foreground, &facts);
+        assert_eq!(trace.decision, Decision::Apply(Action::Boost));
+    }
+
+    #[test]
+    fn sweep_applies_its_action() {
+        let trace = decide(Trigger::Sweep(Action::Restore), &Facts::default());
+        assert_eq!(trace.decision, Decision::Apply(Action::Restore));
+
+        let facts = Facts {
+            whitelisted: true,
+            ..Default::default()
+        };
+        let trace = decide(Trigger::Sweep(Action::Throttle), &facts);
+        assert_eq!(trace.decision, Decision::Skip(Skip::Whitelisted));
+        let trace = decide(Trigger::Requested(Action::Boost), &facts);
+        assert_eq!(trace.decision, Decision::Skip(Skip::Whitelisted));
+    }
+
+    #[test]
+    fn pause_restores_whatever_was_changed() {
+        let facts = Facts {
+            whitelisted: true,
+            ..Default::default()
+        };
+        let trace = decide(Trigger::Paused, &facts);
+        assert_eq!(trace.decision, Decision::Apply(Action::Restore));
+        assert_eq!(trace.rule, None);
+    }
+
+    #[test]
+    fn restores_while_paused() {
+        let throttle = Decision::Apply(Action::Throttle);
+        let restore = Decision::Apply(Action::Restore);
+        assert_eq!(hold_back(throttle, false, true), Outcome::Paused);
+        assert_eq!(hold_back(restore, false, true), Outcome::Applied);
+        assert_eq!(hold_back(restore, true, true), Outcome::DryRun);
+        let skip = Decision::Skip(Skip::Whitelisted);
+        assert_eq!(hold_back(skip, true, false), Outcome::Skipped);
+    }
+
+    #[test]
+    fn record_is_one_json_line() {
+        let record = Record {
+            time: 1_700_000_000_000,
+            pid: 1234,
+            parent: Some(1000),
+            name: "game.exe".to_string(),
+            trigger: Trigger::Sweep(Action::Throttle),
+            action: None,
+            reason: "whitelisted",
+            outcome: Outcome::Skipped,
+            error: None,
+            latency_us: 120,
+        };
+
+        let line = serde_json::to_string(&record).unwrap();
+        assert!(!line.contains('\n'));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            json!({
+                "time": 1_700_000_000_000_u64,
+                "pid": 1234,
+                "parent": 1000,
+                "name": "game.exe",
+                "trigger": { "sweep": "throttle" },
+                "action": null,
+                "reason": "whitelisted",
+                "outcome": "skipped",
+                "latency_us": 120,
+            })
+        );
+    }
+
+    #[test]
+    fn query_filters_records() {
+        let record = |time, pid, name: &str| Record {
+            time,
+            pid,
+            parent: None,
+            name: name.to_string(),
+            trigger: Trigger::Background,
+            action: Some(Action::Throttle),
+            reason: "default",
+            outcome: Outcome::Applied,
+            error: None,
+            latency_us: 0,
+        };
+        let records = [
+            record(1000, 1, "Game.exe"),
+            record(2000, 2, "game.exe"),
+            record(3000, 3, "other.exe"),
+        ];
+        let pids = |query: Query| {
+            records
+                .iter()
+                .filter(|record| query.matches(record))
+                .map(|record| record.pid)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pids(Query::default()), [1, 2, 3]);
+        let by_name = Query {
+            name: Some("GAME.EXE".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(pids(by_name), [1, 2]);
+        let by_time = Query {
+            since: Some(2000),
+            until: Some(3000),
+            ..Default::default()
+        };
+        assert_eq!(pids(by_time), [2]);
+        let by_pid = Query {
+            pid: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(pids(by_pid), [3]);
+    }
+}
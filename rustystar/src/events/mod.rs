@@ -2,18 +2,57 @@ use std::error::Error;
 use std::slice;
 
 use spdlog::info;
-use win32_ecoqos::utils::Processes;
 
 use windows::Win32::Foundation::{HWND, LPARAM};
 use windows::Win32::UI::Accessibility::{HWINEVENTHOOK, SetWinEventHook};
+use windows::Win32::UI::Shell::{
+    QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, SHQueryUserNotificationState,
+};
 use windows::Win32::UI::WindowsAndMessaging::{
-    DispatchMessageW, EVENT_SYSTEM_FOREGROUND, EnumChildWindows, GetMessageW,
+    DispatchMessageW, EVENT_SYSTEM_FOREGROUND, EnumChildWindows, GetForegroundWindow, GetMessageW,
     GetWindowThreadProcessId, MSG, WINEVENT_OUTOFCONTEXT, WINEVENT_SKIPOWNPROCESS,
 };
 
 use windows::core::BOOL;
 
-use crate::PID_SENDER;
+use crate::WINDOW_SENDER;
+use crate::utils::image_names::image_name_is;
+
+/// a window brought to the foreground, sent by the hook as is
+#[derive(Debug, Clone, Copy)]
+pub struct ForegroundWindow {
+    /// `HWND` as an integer, pointers can't be sent between threads
+    hwnd: isize,
+    /// process owning the window, `ApplicationFrameHost.exe` for UWP apps
+    owner: u32,
+}
+
+impl ForegroundWindow {
+    fn of(hwnd: HWND) -> Option<Self> {
+        let mut owner = 0_u32;
+        if unsafe { GetWindowThreadProcessId(hwnd, Some(&mut owner as _)) } == 0 {
+            return None;
+        }
+        Some(Self {
+            hwnd: hwnd.0 as isize,
+            owner,
+        })
+    }
+
+    /// process behind the window, UWP apps are resolved to the real process behind their frame
+    ///
+    /// The window may be gone already, its owner is returned then.
+    pub fn process_id(self) -> u32 {
+        if image_name_is(self.owner, "ApplicationFrameHost.exe") {
+            let real_pid = try_find_uwp_process(self.owner, HWND(self.hwnd as _));
+            if real_pid != 0 {
+                return real_pid;
+            }
+        }
+        self.owner
+    }
+}
+
 pub fn enter_event_loop() -> Result<(), Box<dyn Error + Send + Sync>> {
     unsafe extern "system" fn hook(
         _hwineventhook: HWINEVENTHOOK,
@@ -24,30 +63,10 @@ pub fn enter_event_loop() -> Result<(), Box<dyn Error + Send + Sync>> {
         _ideventthread: u32,
         _dwmseventtime: u32,
     ) {
-        unsafe {
-            let mut process_id = 0_u32;
-
-            if GetWindowThreadProcessId(window_thread, Some(&mut process_id as _)) == 0 {
-                return;
-            }
-
-            let is_uwp = {
-                Processes::try_new().is_ok_and(|mut procs| {
-                    procs
-                        .find(|p| p.process_id == process_id)
-                        .is_some_and(|p| p.process_name == "ApplicationFrameHost.exe")
-                })
-            };
-            if is_uwp {
-                let real_pid = try_find_uwp_process(process_id, window_thread);
-                if real_pid != 0 {
-                    process_id = real_pid
-                }
-            }
-
-            if let Some(tx) = PID_SENDER.get() {
-                let _ = tx.send(process_id);
-            }
+        // runs on the thread dispatching window messages, anything slow is left to the receiver
+        if let (Some(window), Some(tx)) = (ForegroundWindow::of(window_thread), WINDOW_SENDER.get())
+        {
+            let _ = tx.send(window);
         }
     }
 
@@ -86,6 +105,23 @@ pub fn enter_event_loop() -> Result<(), Box<dyn Error + Send + Sync>> {
     Ok(())
 }
 
+/// process owning the foreground window
+pub fn foreground_process_id() -> Option<u32> {
+    let hwnd = unsafe { GetForegroundWindow() };
+    if hwnd.is_invalid() {
+        return None;
+    }
+    ForegroundWindow::of(hwnd).map(ForegroundWindow::process_id)
+}
+
+/// whether a fullscreen app, e.g. a game or presentation, is running
+pub fn fullscreen_running() -> bool {
+    matches!(
+        unsafe { SHQueryUserNotificationState() },
+        Ok(QUNS_BUSY) | Ok(QUNS_RUNNING_D3D_FULL_SCREEN)
+    )
+}
+
 fn try_find_uwp_process(process_id: u32, window_thread: HWND) -> u32 {
     unsafe extern "system" fn find_match(hwnd: HWND, ctx_ptr: LPARAM) -> BOOL {
         let ctx = unsafe { slice::from_raw_parts_mut(ctx_ptr.0 as _, 2) };
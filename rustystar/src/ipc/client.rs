@@ -0,0 +1,42 @@
+use std::io;
+
+use compio::BufResult;
+use compio::io::AsyncWriteExt;
+use serde_json::Value;
+
+use crate::ipc::protocol::{RpcError, parse_response, request};
+use crate::ipc::{platform, read_line};
+
+/// connection to the control API of the running instance
+pub struct Client {
+    stream: platform::Stream,
+    pending: Vec<u8>,
+    next_id: u64,
+}
+
+impl Client {
+    pub async fn connect() -> io::Result<Self> {
+        Ok(Self {
+            stream: platform::connect().await?,
+            pending: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// call `method`, errors of the running instance are `Ok(Err(_))`
+    pub async fn call(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> io::Result<Result<Value, RpcError>> {
+        self.next_id += 1;
+        let line = request(self.next_id, method, params);
+        let BufResult(written, _) = self.stream.write_all(line.into_bytes()).await;
+        written?;
+
+        let Some(line) = read_line(&mut self.stream, &mut self.pending).await? else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        parse_response(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
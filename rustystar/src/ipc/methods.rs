@@ -0,0 +1,127 @@
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use spdlog::info;
+use win32_ecoqos::utils::Processes;
+
+use crate::CURRENT_FOREGROUND_PID;
+use crate::bypass::whitelisted;
+use crate::config::layer::Sources;
+use crate::config::watch::request_reload;
+use crate::decision::{
+    Action, DRY_RUN, Facts, Outcome, Query, Subject, Trigger, decide, execute, history, touched,
+};
+use crate::ipc::protocol::{RpcError, params};
+use crate::pause::{is_paused, pause, remaining, resume};
+use crate::service::is_listening;
+use crate::stats::{ReportQuery, report};
+use crate::utils::toggle_all;
+
+#[derive(Deserialize)]
+struct Pid {
+    pid: u32,
+}
+
+#[derive(Deserialize)]
+struct Pause {
+    /// resume automatically after this many seconds
+    #[serde(default)]
+    seconds: Option<u64>,
+}
+
+/// methods of the control API
+pub async fn dispatch(sources: &Sources, method: &str, args: Value) -> Result<Value, RpcError> {
+    match method {
+        "status" => Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "pid": std::process::id(),
+            "paused": is_paused(),
+            "paused_seconds_left": remaining().map(|left| left.as_secs()),
+            "dry_run": DRY_RUN.load(Ordering::Acquire),
+            "listening": is_listening(),
+            "foreground_pid": CURRENT_FOREGROUND_PID.load(Ordering::Acquire),
+        })),
+        "pause" => {
+            let Pause { seconds } = params(args)?;
+            let duration = seconds.map(Duration::from_secs);
+            _ = compio::runtime::spawn_blocking(move || pause(duration)).await;
+            Ok(json!({ "paused": true, "paused_seconds_left": seconds }))
+        }
+        "resume" => {
+            _ = compio::runtime::spawn_blocking(resume).await;
+            Ok(json!({ "paused": false }))
+        }
+        "reload" => {
+            // report invalid config to the caller, the watcher would only log it
+            sources.load().await.map_err(RpcError::failed)?;
+            request_reload();
+            Ok(json!({ "reloading": true }))
+        }
+        // without a pid, every process but whitelisted ones is handed back
+        "restore" if args.get("pid").is_none_or(Value::is_null) => {
+            compio::runtime::spawn_blocking(|| toggle_all(None))
+                .await
+                .map_err(|_| RpcError::failed("failed to restore processes"))?
+                .map_err(RpcError::failed)?;
+            info!("restore all processes by control API");
+            Ok(json!({ "all": true }))
+        }
+        "throttle" | "boost" | "restore" => {
+            let Pid { pid } = params(args)?;
+            let action = match method {
+                "throttle" => Action::Throttle,
+                "boost" => Action::Boost,
+                _ => Action::Restore,
+            };
+            compio::runtime::spawn_blocking(move || request(pid, action))
+                .await
+                .map_err(|_| RpcError::failed(format!("failed to {method} process {pid}")))??;
+            info!("{method} process {pid} by control API");
+            Ok(json!({ "pid": pid }))
+        }
+        "touched" => {
+            let touched = touched()
+                .into_iter()
+                .map(|(pid, touched)| {
+                    let name = touched.name.to_string_lossy();
+                    json!({ "pid": pid, "name": name, "action": touched.action })
+                })
+                .collect::<Vec<_>>();
+            Ok(json!(touched))
+        }
+        "decisions" => {
+            let query = params::<Query>(args)?;
+            Ok(json!(history(&query)))
+        }
+        "report" => {
+            let query = params::<ReportQuery>(args)?;
+            Ok(json!(report(&query)))
+        }
+        // the connection is switched to streaming by the server
+        "subscribe" => Ok(json!({ "subscribed": true })),
+        _ => Err(RpcError::method_not_found(method)),
+    }
+}
+
+/// apply `action` to a process like any other decision, it's held back or skipped the same way
+fn request(pid: u32, action: Action) -> Result<(), RpcError> {
+    let received = Instant::now();
+    let process = Processes::try_new()
+        .map_err(RpcError::failed)?
+        .find(|process| process.process_id == pid)
+        .ok_or_else(|| RpcError::failed(format!("no process {pid}")))?;
+
+    let facts = Facts {
+        whitelisted: whitelisted(&process.process_name),
+        ..Default::default()
+    };
+    let trace = decide(Trigger::Requested(action), &facts);
+    match execute(Subject::from(&process), &trace, received).map_err(RpcError::failed)? {
+        Outcome::Skipped => Err(RpcError::failed("process is whitelisted")),
+        Outcome::DryRun => Err(RpcError::failed("dry-run, only logged")),
+        Outcome::Paused => Err(RpcError::failed("throttling is paused")),
+        Outcome::Applied | Outcome::Failed => Ok(()),
+    }
+}
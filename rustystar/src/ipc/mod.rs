@@ -0,0 +1,112 @@
+use std::io;
+use std::rc::Rc;
+
+use compio::BufResult;
+use compio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use kanal::AsyncReceiver;
+use serde_json::Value;
+use spdlog::{debug, info, warn};
+
+use crate::bus::{Stamped, subscribe};
+use crate::config::layer::Sources;
+use crate::ipc::protocol::{RpcError, method, notification, respond};
+
+mod client;
+mod methods;
+pub mod protocol;
+
+#[cfg(windows)]
+#[path = "pipe.rs"]
+mod platform;
+#[cfg(unix)]
+#[path = "socket.rs"]
+mod platform;
+
+pub use client::Client;
+pub use platform::endpoint;
+
+/// messages are small, longer lines are rejected instead of buffered forever
+const MAX_LINE: usize = 64 * 1024;
+
+/// serve the control API in background, only the current user can connect
+pub fn start_control_server(sources: Sources) {
+    let sources = Rc::new(sources);
+    let handler = Rc::new(async move |method: &str, params: Value| {
+        methods::dispatch(&sources, method, params).await
+    });
+
+    compio::runtime::spawn(async move {
+        match endpoint() {
+            Ok(endpoint) => info!("control API listening on {endpoint:?}"),
+            Err(e) => {
+                warn!("control API unavailable: {e}");
+                return;
+            }
+        }
+        if let Err(e) = platform::listen(handler).await {
+            warn!("control API stopped: {e}");
+        }
+    })
+    .detach();
+}
+
+/// answer requests of one client until it disconnects
+async fn serve_connection<S, H>(mut stream: S, handler: Rc<H>) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+    H: AsyncFn(&str, Value) -> Result<Value, RpcError>,
+{
+    let mut pending = Vec::new();
+    while let Some(line) = read_line(&mut stream, &mut pending).await? {
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        // subscribe before answering, no event after the answer is missed
+        let events = (method(&line).as_deref() == Some("subscribe")).then(subscribe);
+        if let Some(response) = respond(&line, &*handler).await {
+            let BufResult(written, _) = stream.write_all(response.into_bytes()).await;
+            written?;
+        }
+        if let Some(events) = events {
+            return stream_events(stream, events).await;
+        }
+    }
+
+    debug!("control client disconnected");
+    Ok(())
+}
+
+/// send events as notifications until the client disconnects
+async fn stream_events<S: AsyncWrite>(
+    mut stream: S,
+    events: AsyncReceiver<Stamped>,
+) -> io::Result<()> {
+    debug!("control client subscribed to events");
+    while let Ok(event) = events.recv().await {
+        let line = notification("event", &event);
+        let BufResult(written, _) = stream.write_all(line.into_bytes()).await;
+        written?;
+    }
+    Ok(())
+}
+
+/// next line from `stream`, `None` once it's closed
+async fn read_line<S: AsyncRead>(
+    stream: &mut S,
+    pending: &mut Vec<u8>,
+) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        if let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            return Ok(Some(pending.drain(..=end).collect()));
+        }
+        if pending.len() > MAX_LINE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+        }
+
+        let BufResult(read, buf) = stream.read(Vec::with_capacity(4096)).await;
+        if read? == 0 {
+            return Ok(None);
+        }
+        pending.extend_from_slice(&buf);
+    }
+}
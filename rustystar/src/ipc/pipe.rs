@@ -0,0 +1,142 @@
+use std::ffi::c_void;
+use std::io;
+use std::os::windows::io::{FromRawHandle, OwnedHandle};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use compio::fs::named_pipe::{ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions};
+use compio::time::sleep;
+use serde_json::Value;
+use spdlog::debug;
+use windows::Win32::Foundation::{ERROR_PIPE_BUSY, HANDLE, HLOCAL, LocalFree};
+use windows::Win32::Security::Authorization::{
+    ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+};
+use windows::Win32::Security::{
+    GetTokenInformation, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER,
+    TokenUser,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+use windows::core::{HSTRING, PWSTR};
+
+use crate::ipc::protocol::RpcError;
+use crate::ipc::serve_connection;
+
+/// pipe of the current user, pipe names are shared by all sessions
+pub fn endpoint() -> io::Result<PathBuf> {
+    current_user_sid().map(|sid| pipe_name(&sid))
+}
+
+fn pipe_name(sid: &str) -> PathBuf {
+    PathBuf::from(format!(r"\\.\pipe\RustyStar-{sid}"))
+}
+
+pub type Stream = NamedPipeClient;
+
+/// connect to the running instance, which may be busy accepting another client
+pub async fn connect() -> io::Result<Stream> {
+    let name = endpoint()?;
+    let mut retries = 10;
+    loop {
+        match ClientOptions::new().open(&name).await {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) && retries > 0 => {
+                retries -= 1;
+                sleep(Duration::from_millis(50)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+pub async fn listen<H>(handler: Rc<H>) -> io::Result<()>
+where
+    H: AsyncFn(&str, Value) -> Result<Value, RpcError> + 'static,
+{
+    let sid = current_user_sid()?;
+    let name = pipe_name(&sid);
+    let descriptor = UserOnly::new(&sid)?;
+
+    // fail instead of sharing the name with whoever created it first
+    let mut server = descriptor.create(ServerOptions::new().first_pipe_instance(true), &name)?;
+    loop {
+        server.connect().await?;
+        debug!("control client connected");
+
+        let connected = std::mem::replace(
+            &mut server,
+            descriptor.create(&mut ServerOptions::new(), &name)?,
+        );
+        compio::runtime::spawn(serve_connection(connected, handler.clone())).detach();
+    }
+}
+
+/// SID of the user running RustyStar, e.g. `S-1-5-21-...`
+fn current_user_sid() -> io::Result<String> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+        let _defer = OwnedHandle::from_raw_handle(token.0);
+
+        let mut needed = 0_u32;
+        _ = GetTokenInformation(token, TokenUser, None, 0, &mut needed);
+        // `TOKEN_USER` holds pointers, keep it aligned
+        let mut buffer = vec![0_u64; (needed as usize).div_ceil(size_of::<u64>())];
+        GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr() as _),
+            needed,
+            &mut needed,
+        )?;
+        let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &mut sid)?;
+        let string = sid.to_string();
+        _ = LocalFree(Some(HLOCAL(sid.0 as _)));
+        string.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// security descriptor granting access to one user only
+struct UserOnly(PSECURITY_DESCRIPTOR);
+
+impl UserOnly {
+    fn new(sid: &str) -> io::Result<Self> {
+        // protected DACL with a single entry, inherited entries don't apply
+        let sddl = HSTRING::from(format!("D:P(A;;GA;;;{sid})"));
+        let mut descriptor = PSECURITY_DESCRIPTOR::default();
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                &sddl,
+                SDDL_REVISION_1,
+                &mut descriptor,
+                None,
+            )?;
+        }
+        Ok(Self(descriptor))
+    }
+
+    fn create(&self, options: &mut ServerOptions, name: &Path) -> io::Result<NamedPipeServer> {
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.0.0,
+            bInheritHandle: false.into(),
+        };
+        unsafe {
+            options
+                .reject_remote_clients(true)
+                .create_with_security_attributes_raw(
+                    name,
+                    &mut attributes as *mut SECURITY_ATTRIBUTES as *mut c_void,
+                )
+        }
+    }
+}
+
+impl Drop for UserOnly {
+    fn drop(&mut self) {
+        _ = unsafe { LocalFree(Some(HLOCAL(self.0.0))) };
+    }
+}
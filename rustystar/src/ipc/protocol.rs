@@ -0,0 +1,257 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// a JSON-RPC 2.0 request, one per line
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// `None` for notifications, which are not answered
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Response {
+    jsonrpc: String,
+    id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const INVALID_REQUEST: i32 = -32600;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    /// the method failed, e.g. config is invalid or a process can't be accessed
+    pub const FAILED: i32 = -32000;
+
+    pub fn new(code: i32, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(Self::METHOD_NOT_FOUND, format!("unknown method `{method}`"))
+    }
+
+    pub fn failed(message: impl ToString) -> Self {
+        Self::new(Self::FAILED, message)
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl Error for RpcError {}
+
+/// request line calling `method`
+pub fn request(id: u64, method: &str, params: Value) -> String {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    });
+    request.to_string() + "\n"
+}
+
+/// notification line, e.g. an event for subscribers
+pub fn notification(method: &str, params: &impl Serialize) -> String {
+    let notification = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+    });
+    notification.to_string() + "\n"
+}
+
+/// method of a request line, without validating the rest of it
+pub fn method(line: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Method {
+        method: String,
+    }
+
+    serde_json::from_slice::<Method>(line)
+        .ok()
+        .map(|request| request.method)
+}
+
+/// result or error of a response line
+pub fn parse_response(line: &[u8]) -> serde_json::Result<Result<Value, RpcError>> {
+    let response = serde_json::from_slice::<Response>(line)?;
+    Ok(match response.error {
+        Some(error) => Err(error),
+        None => Ok(response.result.unwrap_or_default()),
+    })
+}
+
+/// deserialize `params` of a method
+pub fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    // methods without params accept them omitted
+    let params = if params.is_null() {
+        Value::Object(Default::default())
+    } else {
+        params
+    };
+    serde_json::from_value(params).map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e))
+}
+
+/// answer a request `line` with `handler`, `None` for notifications
+pub async fn respond(
+    line: &[u8],
+    handler: &impl AsyncFn(&str, Value) -> Result<Value, RpcError>,
+) -> Option<String> {
+    let request = match serde_json::from_slice::<Value>(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(reply(
+                Value::Null,
+                Err(RpcError::new(RpcError::PARSE_ERROR, e)),
+            ));
+        }
+    };
+    let request = match serde_json::from_value::<Request>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(request) => {
+            let error = RpcError::new(RpcError::INVALID_REQUEST, "`jsonrpc` must be \"2.0\"");
+            return Some(reply(request.id.unwrap_or_default(), Err(error)));
+        }
+        Err(e) => {
+            return Some(reply(
+                Value::Null,
+                Err(RpcError::new(RpcError::INVALID_REQUEST, e)),
+            ));
+        }
+    };
+
+    let result = handler(&request.method, request.params).await;
+    request.id.map(|id| reply(id, result))
+}
+
+fn reply(id: Value, result: Result<Value, RpcError>) -> String {
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = Response {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result,
+        error,
+    };
+    serde_json::to_string(&response).expect("response is always serializable") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+
+    use super::{RpcError, method, notification, params, parse_response, request, respond};
+
+    #[derive(Deserialize)]
+    struct Pid {
+        pid: u32,
+    }
+
+    async fn handler(method: &str, args: Value) -> Result<Value, RpcError> {
+        match method {
+            "ping" => Ok(json!("pong")),
+            "double" => {
+                let Pid { pid } = params(args)?;
+                Ok(json!(pid * 2))
+            }
+            _ => Err(RpcError::method_not_found(method)),
+        }
+    }
+
+    async fn call(line: &str) -> Value {
+        let response = respond(line.as_bytes(), &handler)
+            .await
+            .expect("request has an id");
+        assert!(response.ends_with('\n'));
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[compio::test]
+    async fn answers_requests() {
+        let response = call(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#).await;
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": 1, "result": "pong"})
+        );
+
+        let response =
+            call(r#"{"jsonrpc":"2.0","id":"a","method":"double","params":{"pid":21}}"#).await;
+        assert_eq!(response["id"], "a");
+        assert_eq!(response["result"], 42);
+    }
+
+    #[compio::test]
+    async fn reports_errors() {
+        let response = call("{not json").await;
+        assert_eq!(response["error"]["code"], RpcError::PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+
+        let response = call(r#"{"jsonrpc":"1.0","id":1,"method":"ping"}"#).await;
+        assert_eq!(response["error"]["code"], RpcError::INVALID_REQUEST);
+
+        let response = call(r#"{"jsonrpc":"2.0","id":2,"method":"nope"}"#).await;
+        assert_eq!(response["error"]["code"], RpcError::METHOD_NOT_FOUND);
+        assert_eq!(response["id"], 2);
+
+        let response = call(r#"{"jsonrpc":"2.0","id":3,"method":"double"}"#).await;
+        assert_eq!(response["error"]["code"], RpcError::INVALID_PARAMS);
+    }
+
+    #[compio::test]
+    async fn client_messages_round_trip() {
+        let line = request(5, "double", json!({ "pid": 4 }));
+        let response = respond(line.as_bytes(), &handler).await.unwrap();
+        assert_eq!(parse_response(response.as_bytes()).unwrap(), Ok(json!(8)));
+
+        let line = request(6, "nope", Value::Null);
+        let response = respond(line.as_bytes(), &handler).await.unwrap();
+        let error = parse_response(response.as_bytes()).unwrap().unwrap_err();
+        assert_eq!(error.code, RpcError::METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn notifications_have_no_id() {
+        let line = notification("event", &json!({ "event": "resumed" }));
+        let notification: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["method"], "event");
+        assert_eq!(notification["params"]["event"], "resumed");
+        assert_eq!(notification.get("id"), None);
+
+        assert_eq!(method(line.as_bytes()).as_deref(), Some("event"));
+        assert_eq!(method(b"{}"), None);
+    }
+
+    #[compio::test]
+    async fn notifications_are_not_answered() {
+        let response = respond(br#"{"jsonrpc":"2.0","method":"ping"}"#, &handler).await;
+        assert_eq!(response, None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::{DirBuilder, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use compio::net::{UnixListener, UnixStream};
+use serde_json::Value;
+use spdlog::debug;
+
+use crate::config::{PROJECT_DIR, data_dir};
+use crate::ipc::protocol::RpcError;
+use crate::ipc::serve_connection;
+
+/// socket in a directory of its own, which only the user can access
+///
+/// It's kept apart as the data dir may be shared, e.g. the executable's folder when portable.
+pub fn endpoint() -> io::Result<PathBuf> {
+    let dir = PROJECT_DIR
+        .as_ref()
+        .and_then(|dirs| dirs.runtime_dir())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(data_dir);
+    Ok(dir.join("ipc").join("rustystar.sock"))
+}
+
+pub type Stream = UnixStream;
+
+pub async fn connect() -> io::Result<Stream> {
+    UnixStream::connect(endpoint()?).await
+}
+
+pub async fn listen<H>(handler: Rc<H>) -> io::Result<()>
+where
+    H: AsyncFn(&str, Value) -> Result<Value, RpcError> + 'static,
+{
+    listen_at(&endpoint()?, handler).await
+}
+
+async fn listen_at<H>(path: &Path, handler: Rc<H>) -> io::Result<()>
+where
+    H: AsyncFn(&str, Value) -> Result<Value, RpcError> + 'static,
+{
+    serve(bind(path).await?, handler).await
+}
+
+/// bind `path`, its directory is dedicated to it and restricted to the user
+///
+/// A socket left behind by an instance which didn't exit cleanly is replaced,
+/// anything else at `path` is kept and binding fails.
+async fn bind(path: &Path) -> io::Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        // only the socket's own directory is restricted, not the ones above
+        if let Some(base) = dir.parent() {
+            std::fs::create_dir_all(base)?;
+        }
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        // created before, or by someone else, which fails if it's not ours
+        std::fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+    }
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{path:?} exists and isn't a socket"),
+            ));
+        }
+        Ok(_) if std::os::unix::net::UnixStream::connect(path).is_ok() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{path:?} is served by another instance"),
+            ));
+        }
+        Ok(_) => std::fs::remove_file(path)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let listener = UnixListener::bind(path).await?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+async fn serve<H>(listener: UnixListener, handler: Rc<H>) -> io::Result<()>
+where
+    H: AsyncFn(&str, Value) -> Result<Value, RpcError> + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await?;
+        debug!("control client connected");
+        compio::runtime::spawn(serve_connection(stream, handler.clone())).detach();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use compio::BufResult;
+    use compio::io::{AsyncRead, AsyncWriteExt};
+    use compio::net::UnixStream;
+    use serde_json::{Value, json};
+
+    use super::{bind, serve};
+    use crate::ipc::protocol::RpcError;
+
+    async fn echo(method: &str, params: Value) -> Result<Value, RpcError> {
+        Ok(json!({ "method": method, "params": params }))
+    }
+
+    fn socket_path(test: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("rustystar-{test}-{}", std::process::id()))
+            .join("ipc")
+            .join("rustystar.sock")
+    }
+
+    #[compio::test]
+    async fn serves_requests_over_socket() {
+        let path = socket_path("serve");
+        // bound before returning, clients can connect right away
+        let listener = bind(&path).await.unwrap();
+        compio::runtime::spawn(serve(listener, Rc::new(echo))).detach();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        let request = "{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"status\"}\n";
+        let BufResult(written, _) = stream.write_all(request.as_bytes().to_vec()).await;
+        written.unwrap();
+
+        let BufResult(read, buf) = stream.read(Vec::with_capacity(1024)).await;
+        assert!(read.unwrap() > 0);
+        let response: Value = serde_json::from_slice(buf.trim_ascii()).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["method"], "status");
+
+        _ = std::fs::remove_dir_all(path.parent().unwrap().parent().unwrap());
+    }
+
+    #[compio::test]
+    async fn only_stale_sockets_are_replaced() {
+        let path = socket_path("stale");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        std::fs::write(&path, "not a socket").unwrap();
+        let error = bind(&path).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"not a socket");
+        std::fs::remove_file(&path).unwrap();
+
+        let listener = bind(&path).await.unwrap();
+        let error = bind(&path).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        // the socket is left behind, as by a crash
+        drop(listener);
+        bind(&path).await.unwrap();
+
+        _ = std::fs::remove_dir_all(path.parent().unwrap().parent().unwrap());
+    }
+}
@@ -1,15 +1,25 @@
 use std::ffi::OsString;
-use std::sync::OnceLock;
 use std::sync::atomic::AtomicU32;
+use std::sync::{LazyLock, OnceLock, RwLock};
 
 use ahash::AHashSet;
 use kanal::Sender;
 
+use crate::events::ForegroundWindow;
+
+pub mod bus;
 pub mod bypass;
+pub mod cli;
 pub mod config;
+pub mod decision;
 pub mod events;
+pub mod ipc;
 pub mod logging;
+pub mod metrics;
+pub mod pause;
 pub mod privilege;
+pub mod service;
+pub mod stats;
 pub mod utils;
 
 #[cfg(feature = "auto-launch")]
@@ -17,9 +27,10 @@ pub mod auto_launch;
 #[cfg(feature = "hide-to-tray")]
 pub mod tray;
 
-pub static PID_SENDER: OnceLock<Sender<u32>> = OnceLock::new();
+/// foreground changes, from the event hook to the foreground listener
+pub static WINDOW_SENDER: OnceLock<Sender<ForegroundWindow>> = OnceLock::new();
 
-/// don't touch processes in whitelist
-pub static WHITELIST: OnceLock<AHashSet<OsString>> = OnceLock::new();
+/// don't touch processes in whitelist, replaced on config reload
+pub static WHITELIST: LazyLock<RwLock<AHashSet<OsString>>> = LazyLock::new(Default::default);
 
 pub static CURRENT_FOREGROUND_PID: AtomicU32 = AtomicU32::new(0);
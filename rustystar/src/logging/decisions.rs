@@ -0,0 +1,43 @@
+use std::sync::Mutex;
+
+use spdlog::warn;
+
+use crate::config::{DecisionLog, decision_log_file};
+use crate::decision::Record;
+use crate::logging::rotate::RotatingFile;
+
+/// `None` while the decision log is disabled
+static DECISION_LOG: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+/// start or stop writing the decision log, limits are replaced on config reload
+pub fn configure_decision_log(config: &DecisionLog) {
+    let Ok(mut log) = DECISION_LOG.lock() else {
+        return;
+    };
+    *log = config.enabled.then(|| {
+        let max_size = config.max_size_mb.saturating_mul(1024 * 1024);
+        RotatingFile::new(decision_log_file(), max_size, config.keep)
+    });
+}
+
+/// append `record` as a JSON line, if the decision log is enabled
+pub fn write_decision(record: &Record) {
+    let Ok(mut log) = DECISION_LOG.lock() else {
+        return;
+    };
+    let Some(file) = log.as_mut() else {
+        return;
+    };
+
+    let mut line = match serde_json::to_vec(record) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("failed to serialize decision: {e}");
+            return;
+        }
+    };
+    line.push(b'\n');
+    if let Err(e) = file.write(&line) {
+        warn!("failed to write decision log: {e}");
+    }
+}
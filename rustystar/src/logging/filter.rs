@@ -0,0 +1,184 @@
+use std::sync::{Arc, RwLock};
+
+use spdlog::sink::{GetSinkProp, Sink, SinkProp};
+use spdlog::{Level, LevelFilter, Record};
+
+use crate::config::{LogLevel, Logging, ModuleLevel};
+
+/// where a [`FilterSink`] writes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Console,
+    File,
+}
+
+/// console level unless configured, debug builds are more verbose
+const CONSOLE_DEFAULT: LogLevel = if cfg!(debug_assertions) {
+    LogLevel::Debug
+} else {
+    LogLevel::Info
+};
+
+/// levels in effect, replaced by [`configure_logging`]
+struct Levels {
+    console: LogLevel,
+    file: LogLevel,
+    modules: Vec<ModuleLevel>,
+}
+
+static LEVELS: RwLock<Levels> = RwLock::new(Levels {
+    console: CONSOLE_DEFAULT,
+    file: LogLevel::Info,
+    modules: Vec::new(),
+});
+
+/// sink forwarding records which pass the level of their module, or of their output
+pub struct FilterSink {
+    prop: SinkProp,
+    inner: Arc<dyn Sink>,
+    output: Output,
+}
+
+impl FilterSink {
+    pub fn new(inner: Arc<dyn Sink>, output: Output) -> Arc<Self> {
+        Arc::new(Self {
+            prop: SinkProp::default(),
+            inner,
+            output,
+        })
+    }
+}
+
+impl GetSinkProp for FilterSink {
+    fn prop(&self) -> &SinkProp {
+        &self.prop
+    }
+}
+
+impl Sink for FilterSink {
+    // levels are checked in `log`, they depend on the module of a record
+    fn should_log(&self, level: Level) -> bool {
+        self.inner.should_log(level)
+    }
+
+    fn log(&self, record: &Record) -> spdlog::Result<()> {
+        let module_path = record
+            .source_location()
+            .map(|location| location.module_path());
+        let passes = LEVELS.read().map_or(true, |levels| {
+            let level = levels.level(self.output, module_path);
+            level_filter(level).test(record.level())
+        });
+
+        if passes {
+            self.inner.log(record)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&self) -> spdlog::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Levels {
+    /// the most specific module override of `module_path`, otherwise the level of `output`
+    fn level(&self, output: Output, module_path: Option<&str>) -> LogLevel {
+        let module = module_path.and_then(|path| {
+            self.modules
+                .iter()
+                .filter(|module| in_module(path, &module.module))
+                .max_by_key(|module| module.module.len())
+        });
+
+        match (module, output) {
+            (Some(module), _) => module.level,
+            (None, Output::Console) => self.console,
+            (None, Output::File) => self.file,
+        }
+    }
+}
+
+/// whether `path` is `module` or inside it, the crate name of RustyStar may be left out
+fn in_module(path: &str, module: &str) -> bool {
+    let own = path.strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"));
+    [Some(path), own].into_iter().flatten().any(|path| {
+        path.strip_prefix(module)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    })
+}
+
+fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::Off,
+        LogLevel::Critical => LevelFilter::MoreSevereEqual(Level::Critical),
+        LogLevel::Error => LevelFilter::MoreSevereEqual(Level::Error),
+        LogLevel::Warn => LevelFilter::MoreSevereEqual(Level::Warn),
+        LogLevel::Info => LevelFilter::MoreSevereEqual(Level::Info),
+        LogLevel::Debug => LevelFilter::MoreSevereEqual(Level::Debug),
+        LogLevel::Trace => LevelFilter::All,
+    }
+}
+
+/// replace levels of console, file and modules, on startup and config reload
+pub fn configure_logging(config: &Logging) {
+    let console = config.console_level.unwrap_or(CONSOLE_DEFAULT);
+    let most_verbose = [console, config.file_level]
+        .into_iter()
+        .chain(config.modules.iter().map(|module| module.level))
+        .max()
+        .unwrap_or_default();
+
+    if let Ok(mut levels) = LEVELS.write() {
+        *levels = Levels {
+            console,
+            file: config.file_level,
+            modules: config.modules.clone(),
+        };
+    }
+    // records are filtered per module by `FilterSink`, the logger lets all of them through
+    spdlog::default_logger().set_level_filter(level_filter(most_verbose));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Levels, Output, in_module};
+    use crate::config::{LogLevel, ModuleLevel};
+
+    #[test]
+    fn modules_match_by_path() {
+        assert!(in_module("rustystar::events", "events"));
+        assert!(in_module("rustystar::ipc::pipe", "ipc"));
+        assert!(in_module("rustystar::ipc::pipe", "rustystar::ipc::pipe"));
+        assert!(in_module("listen_new_proc::wmi", "listen_new_proc"));
+        assert!(!in_module("rustystar::events_ext", "events"));
+        assert!(!in_module("rustystar::service", "events"));
+    }
+
+    #[test]
+    fn most_specific_override_wins() {
+        let module = |module: &str, level| ModuleLevel {
+            module: module.to_string(),
+            level,
+        };
+        let levels = Levels {
+            console: LogLevel::Info,
+            file: LogLevel::Warn,
+            modules: vec![
+                module("ipc", LogLevel::Trace),
+                module("ipc::pipe", LogLevel::Error),
+            ],
+        };
+
+        let level = |output, path| levels.level(output, Some(path));
+        assert_eq!(
+            level(Output::File, "rustystar::ipc::methods"),
+            LogLevel::Trace
+        );
+        assert_eq!(level(Output::File, "rustystar::ipc::pipe"), LogLevel::Error);
+        assert_eq!(level(Output::Console, "rustystar::events"), LogLevel::Info);
+        assert_eq!(level(Output::File, "rustystar::events"), LogLevel::Warn);
+        assert_eq!(levels.level(Output::File, None), LogLevel::Warn);
+    }
+}
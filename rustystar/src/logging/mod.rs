@@ -2,6 +2,16 @@ use std::fmt::Debug;
 
 use spdlog::{error, warn};
 
+mod decisions;
+mod filter;
+mod rotate;
+mod sink;
+
+pub use decisions::{configure_decision_log, write_decision};
+pub use filter::{FilterSink, Output, configure_logging};
+pub use rotate::RotatingFile;
+pub use sink::{LOG_FILE_SINK, RotatingSink, configure_log_file};
+
 pub fn log_error<E>(e: &E)
 where
     E: Debug,
@@ -0,0 +1,251 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+/// a file appended to, rotated to `<path>.1`, `<path>.2`, ... once it grows over `max_size`
+///
+/// `<path>.1` is the newest rotated file, files beyond `keep` are deleted.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    /// in bytes, `0` never rotates
+    max_size: u64,
+    keep: u32,
+    /// also rotate once the file is older
+    max_age: Option<Duration>,
+    /// gzip rotated files to `<path>.1.gz`, ...
+    compress: bool,
+    /// opened on first write
+    file: Option<File>,
+    size: u64,
+    created: SystemTime,
+    /// gzip of `<path>.1` running in the background
+    compressing: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl RotatingFile {
+    pub fn new(path: PathBuf, max_size: u64, keep: u32) -> Self {
+        Self {
+            path,
+            max_size,
+            keep,
+            max_age: None,
+            compress: false,
+            file: None,
+            size: 0,
+            created: SystemTime::now(),
+            compressing: None,
+        }
+    }
+
+    pub fn max_age(self, max_age: Option<Duration>) -> Self {
+        Self { max_age, ..self }
+    }
+
+    pub fn compress(self, compress: bool) -> Self {
+        Self { compress, ..self }
+    }
+
+    /// replace the settings by those of `other`, keeping track of a running compression
+    pub fn reconfigure(&mut self, other: RotatingFile) {
+        let compressing = self.compressing.take();
+        *self = Self {
+            compressing,
+            ..other
+        };
+    }
+
+    /// path of the `index`-th rotated file before compression, `0` is the current one
+    fn rotated(&self, index: u32) -> PathBuf {
+        match index {
+            0 => self.path.clone(),
+            _ => {
+                let mut path = self.path.clone().into_os_string();
+                path.push(format!(".{index}"));
+                path.into()
+            }
+        }
+    }
+
+    fn compressed(&self, index: u32) -> PathBuf {
+        let mut path = self.rotated(index).into_os_string();
+        path.push(".gz");
+        path.into()
+    }
+
+    /// append `buf` as a whole, rotating before if it would exceed `max_size` or `max_age`
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.open()?,
+        };
+
+        let len = buf.len() as u64;
+        let too_large = self.max_size != 0 && self.size + len > self.max_size;
+        let too_old = self
+            .max_age
+            .is_some_and(|max_age| self.created.elapsed().is_ok_and(|age| age > max_age));
+        if self.size != 0 && (too_large || too_old) {
+            // Windows refuses to rename open files
+            drop(file);
+            self.rotate()?;
+            file = self.open()?;
+        }
+
+        file.write_all(buf)?;
+        self.size += len;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<File> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let metadata = file.metadata()?;
+        self.size = metadata.len();
+        // Windows may hand a new file the creation time of the one just renamed away
+        self.created = match self.size {
+            0 => SystemTime::now(),
+            _ => metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .unwrap_or_else(|_| SystemTime::now()),
+        };
+        Ok(file)
+    }
+
+    /// shift rotated files by one, the current file becomes `<path>.1`
+    fn rotate(&mut self) -> io::Result<()> {
+        let ignore_missing = |result: io::Result<()>| match result {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+
+        if self.keep == 0 {
+            return ignore_missing(fs::remove_file(&self.path));
+        }
+        // `<path>.1` must not be shifted while it is still read,
+        // a failed compression leaves it uncompressed
+        _ = self.wait_compressed();
+        // both variants are shifted, `compress` may have been toggled in between
+        ignore_missing(fs::remove_file(self.rotated(self.keep)))?;
+        ignore_missing(fs::remove_file(self.compressed(self.keep)))?;
+        for index in (1..self.keep).rev() {
+            ignore_missing(fs::rename(self.rotated(index), self.rotated(index + 1)))?;
+            ignore_missing(fs::rename(
+                self.compressed(index),
+                self.compressed(index + 1),
+            ))?;
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+
+        if self.compress {
+            // compressing takes long enough to hold up logging from every thread
+            let (from, to) = (self.rotated(1), self.compressed(1));
+            self.compressing = Some(thread::spawn(move || {
+                gzip(&from, &to)?;
+                fs::remove_file(from)
+            }));
+        }
+        Ok(())
+    }
+
+    /// wait for the compression started by the last rotation
+    fn wait_compressed(&mut self) -> io::Result<()> {
+        match self.compressing.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("compressing log file panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+fn gzip(from: &Path, to: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(from)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(to)?), Compression::fast());
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    use flate2::read::GzDecoder;
+
+    use super::RotatingFile;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rustystar-{name}-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotates_and_keeps_newest() {
+        let dir = temp_dir("rotate");
+        let mut file = RotatingFile::new(dir.join("test.log"), 8, 2);
+        for line in ["aaaa\n", "bbbb\n", "cccc\n", "dddd\n"] {
+            file.write(line.as_bytes()).unwrap();
+        }
+
+        let read = |index| std::fs::read_to_string(file.rotated(index)).ok();
+        assert_eq!(read(0).as_deref(), Some("dddd\n"));
+        assert_eq!(read(1).as_deref(), Some("cccc\n"));
+        assert_eq!(read(2).as_deref(), Some("bbbb\n"));
+        assert_eq!(read(3), None);
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compresses_rotated_files() {
+        let dir = temp_dir("rotate-gzip");
+        let mut file = RotatingFile::new(dir.join("test.log"), 8, 2).compress(true);
+        for line in ["aaaa\n", "bbbb\n", "cccc\n"] {
+            file.write(line.as_bytes()).unwrap();
+        }
+        file.wait_compressed().unwrap();
+
+        let gunzip = |index| {
+            let mut content = String::new();
+            GzDecoder::new(std::fs::File::open(file.compressed(index)).unwrap())
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(gunzip(1), "bbbb\n");
+        assert_eq!(gunzip(2), "aaaa\n");
+        assert!(!file.rotated(1).exists());
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rotates_old_files() {
+        let dir = temp_dir("rotate-age");
+        let mut file =
+            RotatingFile::new(dir.join("test.log"), 0, 1).max_age(Some(std::time::Duration::ZERO));
+        for line in ["aaaa\n", "bbbb\n"] {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            file.write(line.as_bytes()).unwrap();
+        }
+
+        let read = |index| std::fs::read_to_string(file.rotated(index)).ok();
+        assert_eq!(read(0).as_deref(), Some("bbbb\n"));
+        assert_eq!(read(1).as_deref(), Some("aaaa\n"));
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
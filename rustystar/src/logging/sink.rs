@@ -0,0 +1,64 @@
+use std::sync::{Arc, LazyLock, Mutex};
+
+use spdlog::formatter::FormatterContext;
+use spdlog::sink::{GetSinkProp, Sink, SinkProp};
+use spdlog::{Error, Record, StringBuf};
+
+use crate::config::{Logging, log_file};
+use crate::logging::rotate::RotatingFile;
+
+/// sink writing `rustystar.log`, rotation is set by [`configure_log_file`]
+pub static LOG_FILE_SINK: LazyLock<Arc<RotatingSink>> = LazyLock::new(|| {
+    let sink = RotatingSink {
+        prop: SinkProp::default(),
+        file: Mutex::new(rotating_log_file(&Logging::default())),
+    };
+    Arc::new(sink)
+});
+
+/// spdlog sink appending to a [`RotatingFile`]
+pub struct RotatingSink {
+    prop: SinkProp,
+    file: Mutex<RotatingFile>,
+}
+
+impl GetSinkProp for RotatingSink {
+    fn prop(&self) -> &SinkProp {
+        &self.prop
+    }
+}
+
+impl Sink for RotatingSink {
+    fn log(&self, record: &Record) -> spdlog::Result<()> {
+        let mut string_buf = StringBuf::new();
+        let mut ctx = FormatterContext::new();
+        self.prop
+            .formatter()
+            .format(record, &mut string_buf, &mut ctx)?;
+
+        let Ok(mut file) = self.file.lock() else {
+            return Ok(());
+        };
+        file.write(string_buf.as_bytes())
+            .map_err(Error::WriteRecord)
+    }
+
+    /// records are written unbuffered
+    fn flush(&self) -> spdlog::Result<()> {
+        Ok(())
+    }
+}
+
+/// replace rotation settings of `rustystar.log`, on startup and config reload
+pub fn configure_log_file(config: &Logging) {
+    if let Ok(mut file) = LOG_FILE_SINK.file.lock() {
+        file.reconfigure(rotating_log_file(config));
+    }
+}
+
+fn rotating_log_file(config: &Logging) -> RotatingFile {
+    let max_size = config.max_size_mb.saturating_mul(1024 * 1024);
+    RotatingFile::new(log_file(), max_size, config.keep)
+        .max_age(config.rotate.max_age())
+        .compress(config.compress)
+}
@@ -1,58 +1,47 @@
 #![cfg_attr(feature = "hide-to-tray", windows_subsystem = "windows")]
 
 use std::error::Error;
-use std::ffi::OsString;
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
 
-use ahash::AHashSet;
-use spdlog::sink::FileSink;
-use spdlog::{Level, LevelFilter, debug, error, info, trace, warn};
-use win32_ecoqos::process::toggle_efficiency_mode;
-use windows::Win32::UI::Shell::{
-    QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, SHQueryUserNotificationState,
-};
+use spdlog::{Level, LevelFilter, error, info, warn};
 
-use rustystar::bypass::whitelisted;
-use rustystar::config::{Config, PROJECT_DIR};
-use rustystar::events::enter_event_loop;
-use rustystar::logging::log_error;
-use rustystar::privilege::try_enable_se_debug_privilege;
-use rustystar::utils::{ProcTree, process_child_process, singleton_check, toggle_all};
-use rustystar::{CURRENT_FOREGROUND_PID, PID_SENDER, WHITELIST};
+use rustystar::cli::{Cli, Command, already_running, run_command};
+use rustystar::config::layer::Sources;
+use rustystar::config::{Config, Logging, PORTABLE_DIR, log_file};
+use rustystar::ipc::start_control_server;
+use rustystar::logging::{FilterSink, LOG_FILE_SINK, Output, configure_logging};
+use rustystar::service::{apply_config, is_listening, watch_config};
+use rustystar::utils::{singleton_check, toggle_all};
 
 #[compio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let log_path = PROJECT_DIR
-        .as_ref()
-        .map(|proj| proj.data_dir().to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
-    let log_file = log_path.join("rustystar.log");
-
-    let logger = spdlog::default_logger().fork_with(|logger| {
-        logger.set_level_filter(LevelFilter::MoreSevereEqual(if cfg!(debug_assertions) {
-            Level::Debug
-        } else {
-            Level::Info
-        }));
+    let cli = Cli::from_env();
+    let sources = Sources::new(cli.config, cli.overrides).await?;
+    match cli.command {
+        None | Some(Command::Run) => {}
+        Some(command) => return run_command(command, sources, cli.json).await,
+    }
 
-        _ = std::fs::create_dir_all(&log_path);
+    let log_file = log_file();
 
-        let file_level = LevelFilter::MoreSevereEqual(Level::Info);
-        logger.sinks_mut().push(Arc::new(
-            FileSink::builder()
-                .level_filter(file_level)
-                .path(&log_file)
-                .build()?,
-        ));
-        logger.set_flush_level_filter(file_level);
+    let logger = spdlog::default_logger().fork_with(|logger| {
+        // levels and rotation are set as configured once configuration is loaded
+        for console in std::mem::take(logger.sinks_mut()) {
+            logger
+                .sinks_mut()
+                .push(FilterSink::new(console, Output::Console));
+        }
+        logger
+            .sinks_mut()
+            .push(FilterSink::new(LOG_FILE_SINK.clone(), Output::File));
+        logger.set_flush_level_filter(LevelFilter::MoreSevereEqual(Level::Info));
         Ok(())
     })?;
     spdlog::set_default_logger(logger);
+    configure_logging(&Logging::default());
 
     if let Ok(false) = singleton_check() {
-        info!("found existing instance, exiting silently...");
+        info!("found existing instance, exiting...");
+        already_running().await;
         return Ok(());
     }
 
@@ -70,27 +59,23 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         }
     }
 
-    let config = Config::from_profile()
-        .await
-        .expect("failed to load configuration!");
-    info!("loaded configuration: {config:#?}");
-    let Config {
-        #[cfg_attr(not(feature = "auto-launch"), allow(unused))]
-        autostart_on_boot,
-        listen_new_process,
-        listen_foreground_events,
-        throttle_all_startup,
-        system_process,
-        whitelist,
-    } = config;
-
-    #[cfg(feature = "auto-launch")]
-    let _ = rustystar::auto_launch::setup_auto_launch(autostart_on_boot);
-
-    info!("initializing whitelist...");
-    let _ = WHITELIST.set(AHashSet::from_iter(
-        whitelist.into_iter().map(OsString::from),
-    ));
+    if let Err(e) = Config::prepare_profile(&sources.user).await {
+        warn!("failed to update config file: {e}");
+    }
+    let layered = match sources.load().await {
+        Ok(layered) => layered,
+        Err(e) => {
+            error!("failed to load configuration! {e}");
+            return Ok(());
+        }
+    };
+    if let Some(portable_dir) = PORTABLE_DIR.as_ref() {
+        info!("running in portable mode from {portable_dir:?}");
+    }
+    for include in &layered.includes {
+        info!("included {include:?}");
+    }
+    info!("loaded configuration: {:#?}", layered.config);
 
     info!("registering Ctrl-C handler...");
     ctrlc::set_handler(|| {
@@ -100,119 +85,13 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     })?;
 
     #[cfg(feature = "hide-to-tray")]
-    let _ = rustystar::tray::start_tray_service(log_file);
+    let _ = rustystar::tray::start_tray_service(sources.user.clone(), log_file);
 
-    if system_process {
-        match try_enable_se_debug_privilege() {
-            Ok(true) => {
-                info!("SeDebugPrivilege enabled!");
-            }
-            Ok(false) => {
-                warn!("SeDebugPrivilege enabled, but RustyStar wasn't evelated!");
-            }
-            Err(e) => {
-                warn!("SeDebugPrivilege enable failed: {e}");
-            }
-        }
-    } else {
-        info!("skip to enable SeDebugPrivilege");
-    }
+    apply_config(None, &layered.config).await;
 
-    if throttle_all_startup {
-        info!("throtting all processes...");
-        _ = compio::runtime::spawn_blocking(|| toggle_all(Some(true))).await;
-    }
-
-    let mut taskset = Vec::new();
-    if listen_foreground_events.enabled {
-        let (tx, rx) = kanal::bounded_async(64);
-        let _ = PID_SENDER.set(tx.to_sync());
-
-        taskset.push(compio::runtime::spawn_blocking(|| {
-            let _ = enter_event_loop().inspect_err(log_error);
-            Ok(())
-        }));
-
-        info!("listening foreground events...");
-        taskset.push(compio::runtime::spawn(async move {
-            let mut last_pid = None;
-
-            while let Ok(pid) = rx.recv().await {
-                trace!("received: {pid}");
-
-                match last_pid {
-                    // skip boosting
-                    Some(last) if last == pid => {
-                        continue;
-                    }
-                    Some(last_pid) => match unsafe { SHQueryUserNotificationState() } {
-                        Ok(QUNS_BUSY) | Ok(QUNS_RUNNING_D3D_FULL_SCREEN) => {
-                            debug!("detected full screen app! skip throttling");
-                        }
-                        _ => {
-                            _ = compio::runtime::spawn_blocking(move || {
-                                process_child_process(Some(true), last_pid)
-                            })
-                            .await;
-                        }
-                    },
-
-                    None => {}
-                }
-
-                CURRENT_FOREGROUND_PID.store(pid, Ordering::Release);
-                _ = compio::runtime::spawn_blocking(move || {
-                    process_child_process(Some(false), pid)
-                })
-                .await;
-                last_pid = Some(pid);
-            }
-
-            Ok::<(), Box<dyn Error + Send + Sync>>(())
-        }));
-    }
-
-    if listen_new_process.enabled {
-        let blacklist =
-            AHashSet::from_iter(listen_new_process.blacklist.iter().map(OsString::from));
-        info!("listening new processes...");
-        listen_new_proc::listen_process_creation(
-            move |listen_new_proc::Process {
-                      process_id, name, ..
-                  }| {
-                let proc_name = OsString::from(name);
-                match listen_new_process.mode {
-                    rustystar::config::ListenNewProcessMode::Normal => {
-                        if whitelisted(&proc_name) {
-                            return;
-                        }
-
-                        let current_fg = CURRENT_FOREGROUND_PID.load(Ordering::Acquire);
-                        if current_fg != 0
-                            && ProcTree::new()
-                                .is_ok_and(|proc_tree| proc_tree.is_in_tree(current_fg, process_id))
-                        {
-                            debug!("skipping {proc_name:?}: foreground process child");
-                            return;
-                        }
-                    }
-                    rustystar::config::ListenNewProcessMode::BlacklistOnly => {
-                        if !blacklist.contains(&proc_name) {
-                            return;
-                        }
-                    }
-                }
-
-                _ = toggle_efficiency_mode(process_id, Some(true));
-            },
-        )
-        .await?;
-    }
-
-    if !taskset.is_empty() {
-        for task in taskset {
-            _ = task.await;
-        }
+    if is_listening() {
+        start_control_server(sources.clone());
+        watch_config(layered, sources).await;
     } else {
         info!("one-shot mode detected! will leave processes throttled");
     }
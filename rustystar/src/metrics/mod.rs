@@ -0,0 +1,246 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::decision::{Action, Trigger};
+
+mod server;
+
+pub use server::start_metrics_server;
+
+/// upper bounds of latency buckets, in seconds
+const BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// triggers with latency observed, sweeps, requests and pauses aren't caused by an event
+const TRIGGERS: [&str; 3] = ["started", "foreground", "background"];
+const ACTIONS: [&str; 3] = ["throttle", "boost", "restore"];
+
+pub static METRICS: Registry = Registry::new();
+
+/// counters and histograms, updated whether the exporter is enabled or not
+pub struct Registry {
+    /// by action, then ok and error
+    applied: [[AtomicU64; 2]; 3],
+    access_denied: AtomicU64,
+    foreground_changes: AtomicU64,
+    latency: [Histogram; 3],
+}
+
+struct Histogram {
+    /// not cumulative, the last one is `+Inf`
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len() + 1],
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Registry {
+    pub const fn new() -> Self {
+        Self {
+            applied: [const { [const { AtomicU64::new(0) }; 2] }; 3],
+            access_denied: AtomicU64::new(0),
+            foreground_changes: AtomicU64::new(0),
+            latency: [const { Histogram::new() }; 3],
+        }
+    }
+
+    /// an action was applied, `access_denied` if it failed for lack of rights
+    pub fn applied(&self, action: Action, ok: bool, access_denied: bool) {
+        let action = match action {
+            Action::Throttle => 0,
+            Action::Boost => 1,
+            Action::Restore => 2,
+        };
+        self.applied[action][usize::from(!ok)].fetch_add(1, Ordering::Relaxed);
+        if access_denied {
+            self.access_denied.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn foreground_changed(&self) {
+        self.foreground_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// time from receiving an event to having applied the decisions it caused
+    pub fn event_handled(&self, trigger: Trigger, elapsed: Duration) {
+        let trigger = match trigger {
+            Trigger::Started => 0,
+            Trigger::Foreground => 1,
+            Trigger::Background => 2,
+            Trigger::Sweep(_) | Trigger::Requested(_) | Trigger::Paused => return,
+        };
+        self.latency[trigger].observe(elapsed);
+    }
+
+    /// Prometheus text exposition format
+    pub fn render(&self, throttled: usize) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "rustystar_actions_total",
+            "counter",
+            "EcoQoS changes by action and outcome",
+        );
+        for (action, counters) in ACTIONS.iter().zip(&self.applied) {
+            for (outcome, counter) in ["ok", "error"].iter().zip(counters) {
+                _ = writeln!(
+                    out,
+                    "rustystar_actions_total{{action=\"{action}\",outcome=\"{outcome}\"}} {}",
+                    counter.load(Ordering::Relaxed)
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "rustystar_access_denied_total",
+            "counter",
+            "EcoQoS changes denied by Windows",
+        );
+        _ = writeln!(
+            out,
+            "rustystar_access_denied_total {}",
+            self.access_denied.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "rustystar_foreground_changes_total",
+            "counter",
+            "foreground window changes",
+        );
+        _ = writeln!(
+            out,
+            "rustystar_foreground_changes_total {}",
+            self.foreground_changes.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "rustystar_throttled_processes",
+            "gauge",
+            "processes currently throttled by RustyStar",
+        );
+        _ = writeln!(out, "rustystar_throttled_processes {throttled}");
+
+        header(
+            &mut out,
+            "rustystar_event_to_apply_seconds",
+            "histogram",
+            "time from an event to its decisions being applied",
+        );
+        for (trigger, histogram) in TRIGGERS.iter().zip(&self.latency) {
+            let mut count = 0;
+            let bounds = BUCKETS
+                .iter()
+                .map(f64::to_string)
+                .chain(["+Inf".to_string()]);
+            for (bound, bucket) in bounds.zip(&histogram.buckets) {
+                count += bucket.load(Ordering::Relaxed);
+                _ = writeln!(
+                    out,
+                    "rustystar_event_to_apply_seconds_bucket{{trigger=\"{trigger}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+            _ = writeln!(
+                out,
+                "rustystar_event_to_apply_seconds_sum{{trigger=\"{trigger}\"}} {sum}"
+            );
+            _ = writeln!(
+                out,
+                "rustystar_event_to_apply_seconds_count{{trigger=\"{trigger}\"}} {count}"
+            );
+        }
+
+        out
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Registry;
+    use crate::decision::{Action, Trigger};
+
+    #[test]
+    fn renders_counters() {
+        let registry = Registry::new();
+        registry.applied(Action::Throttle, true, false);
+        registry.applied(Action::Throttle, true, false);
+        registry.applied(Action::Boost, false, true);
+        registry.foreground_changed();
+
+        let text = registry.render(7);
+        assert!(text.contains("rustystar_actions_total{action=\"throttle\",outcome=\"ok\"} 2\n"));
+        assert!(text.contains("rustystar_actions_total{action=\"boost\",outcome=\"error\"} 1\n"));
+        assert!(text.contains("rustystar_actions_total{action=\"restore\",outcome=\"ok\"} 0\n"));
+        assert!(text.contains("rustystar_access_denied_total 1\n"));
+        assert!(text.contains("rustystar_foreground_changes_total 1\n"));
+        assert!(text.contains("rustystar_throttled_processes 7\n"));
+        assert!(text.contains("# TYPE rustystar_event_to_apply_seconds histogram\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let registry = Registry::new();
+        registry.event_handled(Trigger::Foreground, Duration::from_millis(3));
+        registry.event_handled(Trigger::Foreground, Duration::from_millis(40));
+        registry.event_handled(Trigger::Foreground, Duration::from_secs(10));
+        registry.event_handled(Trigger::Sweep(Action::Throttle), Duration::from_secs(1));
+
+        let text = registry.render(0);
+        let bucket = |le: &str| {
+            let prefix = format!(
+                "rustystar_event_to_apply_seconds_bucket{{trigger=\"foreground\",le=\"{le}\"}} "
+            );
+            text.lines()
+                .find_map(|line| line.strip_prefix(&prefix))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(bucket("0.001"), "0");
+        assert_eq!(bucket("0.005"), "1");
+        assert_eq!(bucket("0.05"), "2");
+        assert_eq!(bucket("2.5"), "2");
+        assert_eq!(bucket("+Inf"), "3");
+        assert!(
+            text.contains("rustystar_event_to_apply_seconds_count{trigger=\"foreground\"} 3\n")
+        );
+        assert!(
+            text.contains("rustystar_event_to_apply_seconds_sum{trigger=\"foreground\"} 10.043\n")
+        );
+        assert!(text.contains("rustystar_event_to_apply_seconds_count{trigger=\"started\"} 0\n"));
+    }
+}
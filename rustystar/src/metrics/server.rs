@@ -0,0 +1,127 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use compio::BufResult;
+use compio::io::{AsyncRead, AsyncWriteExt};
+use compio::net::{TcpListener, TcpStream};
+use spdlog::{debug, info, warn};
+
+use crate::config::Metrics;
+use crate::decision::throttled_count;
+use crate::metrics::METRICS;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// the server is started on first enable, and only stops answering when disabled later
+static STARTED_PORT: OnceLock<u16> = OnceLock::new();
+
+/// requests are tiny, anything larger isn't a scrape
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// serve metrics on the loopback interface if `config` enables it
+pub fn start_metrics_server(config: &Metrics) {
+    ENABLED.store(config.enabled, Ordering::Release);
+    if !config.enabled {
+        return;
+    }
+
+    let port = *STARTED_PORT.get_or_init(|| {
+        compio::runtime::spawn(serve(config.port)).detach();
+        config.port
+    });
+    if port != config.port {
+        warn!("metrics stay on port {port} until RustyStar is restarted");
+    }
+}
+
+async fn serve(port: u16) {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("failed to serve metrics on {addr}: {e}");
+            return;
+        }
+    };
+    info!("serving metrics on http://{addr}/metrics");
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => compio::runtime::spawn(scrape(stream)).detach(),
+            Err(e) => debug!("failed to accept metrics client: {e}"),
+        }
+    }
+}
+
+async fn scrape(mut stream: TcpStream) {
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let BufResult(read, buf) = stream.read(Vec::with_capacity(1024)).await;
+        match read {
+            Ok(0) | Err(_) => return,
+            Ok(_) => request.extend_from_slice(&buf),
+        }
+    }
+
+    let response = respond(&request, ENABLED.load(Ordering::Acquire), || {
+        METRICS.render(throttled_count())
+    });
+    let BufResult(written, _) = stream.write_all(response.into_bytes()).await;
+    if let Err(e) = written {
+        debug!("failed to answer metrics client: {e}");
+    }
+}
+
+/// HTTP response to `request`, only `GET /metrics` is served
+fn respond(request: &[u8], enabled: bool, render: impl FnOnce() -> String) -> String {
+    let request_line = request
+        .split(|byte| *byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut parts = request_line.split(|byte| *byte == b' ');
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/metrics")) if enabled => ("200 OK", render()),
+        (Some(b"GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+
+    format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::respond;
+
+    #[test]
+    fn serves_metrics_path_only() {
+        let request = b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let response = respond(request, true, || "metric 1\n".to_string());
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 9\r\n"));
+        assert!(response.ends_with("\r\n\r\nmetric 1\n"));
+
+        let response = respond(b"GET / HTTP/1.1\r\n\r\n", true, String::new);
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = respond(b"POST /metrics HTTP/1.1\r\n\r\n", true, String::new);
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+
+    #[test]
+    fn disabled_exporter_serves_nothing() {
+        let request = b"GET /metrics HTTP/1.1\r\n\r\n";
+        let response = respond(request, false, || unreachable!("metrics are disabled"));
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use spdlog::{info, warn};
+
+use crate::bus::{Event, publish};
+use crate::decision::{Action, Facts, PAUSED, Subject, Trigger, decide, execute, take_touched};
+use crate::service::reapply_policy;
+use crate::stats;
+
+/// bumped by every pause and resume, outdated expiry timers are ignored
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// when the current pause ends, `None` if it lasts until resumed
+static UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// processes throttled before pausing, throttled again on resume
+static THROTTLED: LazyLock<Mutex<Vec<(u32, OsString)>>> = LazyLock::new(Default::default);
+
+/// stop throttling for `duration`, or until [`resume`] if `None`
+///
+/// every process RustyStar touched is restored, pausing again only updates the expiry
+pub fn pause(duration: Option<Duration>) {
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+    if let Ok(mut until) = UNTIL.lock() {
+        *until = duration.map(|duration| Instant::now() + duration);
+    }
+
+    if !PAUSED.swap(true, Ordering::AcqRel) {
+        let received = Instant::now();
+        let touched = take_touched();
+        for (pid, touched) in &touched {
+            let subject = Subject {
+                pid: *pid,
+                parent: None,
+                name: &touched.name,
+            };
+            let trace = decide(Trigger::Paused, &Facts::default());
+            if let Err(e) = execute(subject, &trace, received) {
+                warn!("failed to restore {:?}: {e}", touched.name);
+            }
+            // RustyStar's state ended even if restoring failed
+            stats::ended(*pid);
+        }
+        if let Ok(mut throttled) = THROTTLED.lock() {
+            *throttled = touched
+                .into_iter()
+                .filter(|(_, touched)| touched.action == Action::Throttle)
+                .map(|(pid, touched)| (pid, touched.name))
+                .collect();
+        }
+    }
+
+    publish(Event::Paused {
+        seconds: duration.map(|duration| duration.as_secs()),
+    });
+    match duration {
+        Some(duration) => {
+            info!("throttling paused for {duration:?}");
+            std::thread::spawn(move || {
+                std::thread::sleep(duration);
+                if GENERATION.load(Ordering::Acquire) == generation {
+                    info!("pause expired");
+                    resume();
+                }
+            });
+        }
+        None => info!("throttling paused"),
+    }
+}
+
+/// end a pause, and apply the policy again
+pub fn resume() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+    if let Ok(mut until) = UNTIL.lock() {
+        *until = None;
+    }
+    if !PAUSED.swap(false, Ordering::AcqRel) {
+        return;
+    }
+
+    info!("throttling resumed");
+    publish(Event::Resumed);
+    let throttled = THROTTLED
+        .lock()
+        .map(|mut throttled| std::mem::take(&mut *throttled))
+        .unwrap_or_default();
+    reapply_policy(&throttled);
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Acquire)
+}
+
+/// time left until the pause expires, `None` if not paused or paused until resumed
+pub fn remaining() -> Option<Duration> {
+    let until = (*UNTIL.lock().ok()?)?;
+    is_paused().then(|| until.saturating_duration_since(Instant::now()))
+}
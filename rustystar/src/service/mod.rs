@@ -0,0 +1,338 @@
+use std::ffi::OsString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Once, RwLock};
+use std::time::Instant;
+
+use ahash::AHashSet;
+use spdlog::{debug, error, info, trace, warn};
+use win32_ecoqos::utils::Processes;
+
+use crate::bus::{Event, publish};
+use crate::bypass::whitelisted;
+use crate::config::layer::{Layered, Sources};
+use crate::config::watch::ConfigWatcher;
+use crate::config::{Config, ListenNewProcessMode};
+use crate::decision::{
+    Action, DRY_RUN, Facts, Subject, Trigger, decide, execute, set_history_capacity, untouch,
+};
+use crate::events::enter_event_loop;
+use crate::logging::{configure_decision_log, configure_log_file, configure_logging, log_error};
+use crate::metrics::{METRICS, start_metrics_server};
+use crate::privilege::try_enable_se_debug_privilege;
+use crate::stats;
+use crate::utils::{ProcTree, image_names, process_child_process, toggle_all, toggle_by_name};
+use crate::{CURRENT_FOREGROUND_PID, WHITELIST, WINDOW_SENDER};
+
+static LISTEN_FOREGROUND: AtomicBool = AtomicBool::new(false);
+static LISTEN_NEW_PROCESS: AtomicBool = AtomicBool::new(false);
+static THROTTLE_ALL: AtomicBool = AtomicBool::new(false);
+
+/// listeners are started on first enable, and only paused when disabled later
+static FOREGROUND_STARTED: Once = Once::new();
+static NEW_PROCESS_STARTED: Once = Once::new();
+static PROCESS_EXIT_STARTED: Once = Once::new();
+
+#[derive(Default)]
+struct NewProcessFilter {
+    mode: ListenNewProcessMode,
+    blacklist: AHashSet<OsString>,
+}
+
+static NEW_PROCESS_FILTER: LazyLock<RwLock<NewProcessFilter>> = LazyLock::new(Default::default);
+
+/// apply `new` configuration, `old` is the one currently in effect (`None` on startup)
+pub async fn apply_config(old: Option<&Config>, new: &Config) {
+    let Config {
+        config_version: _,
+        include: _,
+        #[cfg_attr(not(feature = "auto-launch"), allow(unused))]
+        autostart_on_boot,
+        listen_new_process,
+        listen_foreground_events,
+        throttle_all_startup,
+        system_process,
+        dry_run,
+        decision_history,
+        whitelist_extra: _,
+        whitelist_remove: _,
+        metrics,
+        decision_log,
+        logging,
+    } = new;
+
+    if *dry_run && old.is_some_and(|old| !old.dry_run) {
+        // hand processes back before only observing them
+        _ = compio::runtime::spawn_blocking(|| toggle_all(None)).await;
+    }
+    DRY_RUN.store(*dry_run, Ordering::Release);
+    if *dry_run && old.is_none_or(|old| !old.dry_run) {
+        info!("dry-run mode, decisions are logged but no process is changed");
+    }
+
+    #[cfg(feature = "auto-launch")]
+    if old.is_none_or(|old| old.autostart_on_boot != *autostart_on_boot) {
+        let _ = crate::auto_launch::setup_auto_launch(*autostart_on_boot);
+    }
+
+    let whitelist = new.whitelist();
+    if old.is_none_or(|old| old.whitelist() != whitelist) {
+        info!("initializing whitelist: {whitelist:?}");
+        let new_set = AHashSet::from_iter(whitelist.iter().map(OsString::from));
+        let old_set = match WHITELIST.write() {
+            Ok(mut current) => std::mem::replace(&mut *current, new_set.clone()),
+            Err(_) => AHashSet::default(),
+        };
+
+        if old.is_some() {
+            // newly whitelisted processes are handed back to the system
+            let added = &new_set - &old_set;
+            // processes removed from whitelist are treated as any other process
+            let removed = &old_set - &new_set;
+
+            if !added.is_empty() {
+                _ = compio::runtime::spawn_blocking(move || toggle_by_name(&added, None)).await;
+            }
+            if !removed.is_empty() && *throttle_all_startup {
+                _ = compio::runtime::spawn_blocking(move || toggle_by_name(&removed, Some(true)))
+                    .await;
+            }
+        }
+    }
+
+    if !system_process {
+        info!("skip to enable SeDebugPrivilege");
+    } else if old.is_none_or(|old| !old.system_process) {
+        match try_enable_se_debug_privilege() {
+            Ok(true) => {
+                info!("SeDebugPrivilege enabled!");
+            }
+            Ok(false) => {
+                warn!("SeDebugPrivilege enabled, but RustyStar wasn't evelated!");
+            }
+            Err(e) => {
+                warn!("SeDebugPrivilege enable failed: {e}");
+            }
+        }
+    }
+
+    THROTTLE_ALL.store(*throttle_all_startup, Ordering::Release);
+    if *throttle_all_startup
+        && old.is_none_or(|old| !old.throttle_all_startup || (old.dry_run && !dry_run))
+    {
+        info!("throtting all processes...");
+        _ = compio::runtime::spawn_blocking(|| toggle_all(Some(true))).await;
+    }
+
+    if let Ok(mut filter) = NEW_PROCESS_FILTER.write() {
+        filter.mode = listen_new_process.mode;
+        filter.blacklist =
+            AHashSet::from_iter(listen_new_process.blacklist.iter().map(OsString::from));
+    }
+
+    LISTEN_NEW_PROCESS.store(listen_new_process.enabled, Ordering::Release);
+    if listen_new_process.enabled {
+        NEW_PROCESS_STARTED.call_once(start_new_process_listener);
+    }
+    PROCESS_EXIT_STARTED.call_once(start_process_exit_listener);
+
+    start_metrics_server(metrics);
+    configure_decision_log(decision_log);
+    set_history_capacity(*decision_history);
+    configure_logging(logging);
+    configure_log_file(logging);
+
+    LISTEN_FOREGROUND.store(listen_foreground_events.enabled, Ordering::Release);
+    if listen_foreground_events.enabled {
+        FOREGROUND_STARTED.call_once(start_foreground_listener);
+    }
+}
+
+/// bring processes in line with the policy again, e.g. after a pause
+///
+/// `throttled` are processes throttled before, they are skipped if the pid was reused
+pub fn reapply_policy(throttled: &[(u32, OsString)]) {
+    let started = Instant::now();
+    if THROTTLE_ALL.load(Ordering::Acquire) {
+        _ = toggle_all(Some(true)).inspect_err(log_error);
+    } else if let Ok(procs) = Processes::try_new() {
+        let trigger = Trigger::Sweep(Action::Throttle);
+        for process in procs {
+            if !throttled
+                .iter()
+                .any(|(pid, name)| *pid == process.process_id && *name == process.process_name)
+            {
+                continue;
+            }
+            let facts = Facts {
+                whitelisted: whitelisted(&process.process_name),
+                ..Default::default()
+            };
+            let trace = decide(trigger, &facts);
+            _ = execute(Subject::from(&process), &trace, started);
+        }
+    }
+
+    let foreground = CURRENT_FOREGROUND_PID.load(Ordering::Acquire);
+    if LISTEN_FOREGROUND.load(Ordering::Acquire) && foreground != 0 {
+        _ = process_child_process(Trigger::Foreground, foreground, started);
+    }
+}
+
+/// whether any listener is enabled, otherwise RustyStar runs in one-shot mode
+pub fn is_listening() -> bool {
+    LISTEN_FOREGROUND.load(Ordering::Acquire) || LISTEN_NEW_PROCESS.load(Ordering::Acquire)
+}
+
+/// keep watching config files, and apply every valid change
+pub async fn watch_config(mut current: Layered, sources: Sources) {
+    let mut watcher = ConfigWatcher::new(sources.watched_files(&current));
+
+    info!("watching config changes...");
+    loop {
+        watcher.changed().await;
+
+        match sources.load().await {
+            Ok(layered) => {
+                watcher.watch(sources.watched_files(&layered));
+
+                if layered.config == current.config {
+                    debug!("config file touched, but nothing changed");
+                } else {
+                    info!("config changed: {:#?}", layered.config);
+                    apply_config(Some(&current.config), &layered.config).await;
+                    publish(Event::ConfigReloaded);
+                }
+                current = layered;
+            }
+            Err(e) => {
+                error!("rejected config change, keeping the previous one: {e}");
+            }
+        }
+    }
+}
+
+fn start_foreground_listener() {
+    let (tx, rx) = kanal::bounded_async(64);
+    let _ = WINDOW_SENDER.set(tx.to_sync());
+
+    compio::runtime::spawn_blocking(|| {
+        let _ = enter_event_loop().inspect_err(log_error);
+    })
+    .detach();
+
+    info!("listening foreground events...");
+    compio::runtime::spawn(async move {
+        let mut last_pid = None;
+
+        while let Ok(window) = rx.recv().await {
+            let received = Instant::now();
+
+            if !LISTEN_FOREGROUND.load(Ordering::Acquire) {
+                last_pid = None;
+                continue;
+            }
+
+            // UWP frames are resolved here, the hook only passes the window on
+            let Ok(pid) = compio::runtime::spawn_blocking(move || window.process_id()).await else {
+                continue;
+            };
+            trace!("received: {pid}");
+
+            match last_pid {
+                // skip boosting
+                Some(last) if last == pid => {
+                    continue;
+                }
+                Some(last_pid) => {
+                    _ = compio::runtime::spawn_blocking(move || {
+                        process_child_process(Trigger::Background, last_pid, received)
+                    })
+                    .await;
+                    METRICS.event_handled(Trigger::Background, received.elapsed());
+                }
+
+                None => {}
+            }
+
+            CURRENT_FOREGROUND_PID.store(pid, Ordering::Release);
+            publish(Event::ForegroundChanged { pid });
+            METRICS.foreground_changed();
+            _ = compio::runtime::spawn_blocking(move || {
+                process_child_process(Trigger::Foreground, pid, received)
+            })
+            .await;
+            METRICS.event_handled(Trigger::Foreground, received.elapsed());
+            last_pid = Some(pid);
+        }
+    })
+    .detach();
+}
+
+fn start_new_process_listener() {
+    info!("listening new processes...");
+    compio::runtime::spawn(async {
+        let _ = listen_new_proc::listen_process_creation(on_process_created)
+            .await
+            .inspect_err(log_error);
+    })
+    .detach();
+}
+
+fn start_process_exit_listener() {
+    compio::runtime::spawn(async {
+        let _ = listen_new_proc::listen_process_exit(on_process_exited)
+            .await
+            .inspect_err(log_error);
+    })
+    .detach();
+}
+
+fn on_process_exited(process: listen_new_proc::Process) {
+    image_names::forget(process.process_id);
+    untouch(process.process_id);
+    stats::ended(process.process_id);
+}
+
+fn on_process_created(
+    listen_new_proc::Process {
+        process_id,
+        parent_process_id,
+        name,
+        ..
+    }: listen_new_proc::Process,
+) {
+    let received = Instant::now();
+    let proc_name = OsString::from(&name);
+    image_names::remember(process_id, proc_name.clone());
+    if !LISTEN_NEW_PROCESS.load(Ordering::Acquire) {
+        return;
+    }
+
+    publish(Event::ProcessCreated {
+        pid: process_id,
+        name,
+    });
+    let Ok(filter) = NEW_PROCESS_FILTER.read() else {
+        return;
+    };
+    let current_fg = CURRENT_FOREGROUND_PID.load(Ordering::Acquire);
+    let facts = Facts {
+        whitelisted: whitelisted(&proc_name),
+        blacklisted: filter.blacklist.contains(&proc_name),
+        // walking the process tree is costly, and only needed in normal mode
+        in_foreground_tree: filter.mode == ListenNewProcessMode::Normal
+            && current_fg != 0
+            && ProcTree::new().is_ok_and(|proc_tree| proc_tree.is_in_tree(current_fg, process_id)),
+        fullscreen: false,
+        mode: filter.mode,
+    };
+    let trace = decide(Trigger::Started, &facts);
+
+    let subject = Subject {
+        pid: process_id,
+        parent: Some(parent_process_id),
+        name: &proc_name,
+    };
+    _ = execute(subject, &trace, received);
+    METRICS.event_handled(Trigger::Started, received.elapsed());
+}
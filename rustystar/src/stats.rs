@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::sync::{LazyLock, Mutex};
+
+use ahash::{AHashMap, AHashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::decision::{Action, Outcome, Record, unix_millis};
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// hourly statistics older than this are dropped
+const RETENTION_HOURS: u64 = 7 * 24;
+
+static STATS: LazyLock<Mutex<Stats>> = LazyLock::new(Default::default);
+
+/// per-executable statistics of this session, fed by every decision
+#[derive(Debug, Default)]
+struct Stats {
+    /// state RustyStar set on a process, by pid
+    processes: AHashMap<u32, Current>,
+    /// by executable name
+    executables: AHashMap<String, Executable>,
+    /// hour of the last pruning
+    pruned: u64,
+}
+
+#[derive(Debug)]
+struct Current {
+    name: String,
+    /// never [`Action::Restore`]
+    action: Action,
+    /// milliseconds since unix epoch
+    since: u64,
+}
+
+#[derive(Debug)]
+struct Executable {
+    first_seen: u64,
+    last_seen: u64,
+    /// by hours since unix epoch
+    hours: BTreeMap<u64, Bucket>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    throttled_ms: u64,
+    boosted_ms: u64,
+    transitions: u64,
+    failures: u64,
+}
+
+/// filter of [`report`]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReportQuery {
+    /// milliseconds since unix epoch, rounded down to the hour
+    pub since: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// start of the report in milliseconds since unix epoch, `None` for the whole session
+    pub since: Option<u64>,
+    pub until: u64,
+    /// most throttled first
+    pub executables: Vec<ExecutableReport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExecutableReport {
+    pub name: String,
+    pub throttled_ms: u64,
+    pub boosted_ms: u64,
+    /// changes between throttled, boosted and restored
+    pub transitions: u64,
+    pub failures: u64,
+    /// milliseconds since unix epoch
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+impl Stats {
+    fn observe(&mut self, record: &Record) {
+        if record.name.is_empty() {
+            return;
+        }
+        let time = record.time;
+        let executable = self
+            .executables
+            .entry(record.name.clone())
+            .or_insert_with(|| Executable {
+                first_seen: time,
+                last_seen: time,
+                hours: BTreeMap::new(),
+            });
+        executable.last_seen = executable.last_seen.max(time);
+
+        match (record.outcome, record.action) {
+            (Outcome::Failed, _) => executable.bucket(time).failures += 1,
+            (Outcome::Applied, Some(action)) => {
+                let previous = self.processes.remove(&record.pid);
+                let previous_action = previous.as_ref().map(|current| current.action);
+                let action = (action != Action::Restore).then_some(action);
+                if previous_action != action {
+                    executable.bucket(time).transitions += 1;
+                }
+                if let Some(previous) = previous {
+                    self.end(previous, time);
+                }
+                if let Some(action) = action {
+                    let name = record.name.clone();
+                    let current = Current {
+                        name,
+                        action,
+                        since: time,
+                    };
+                    self.processes.insert(record.pid, current);
+                }
+            }
+            _ => {}
+        }
+
+        self.prune(time);
+    }
+
+    fn ended(&mut self, pid: u32, time: u64) {
+        if let Some(current) = self.processes.remove(&pid) {
+            self.end(current, time);
+        }
+    }
+
+    /// add the time spent in `current` until `end` to its executable
+    fn end(&mut self, current: Current, end: u64) {
+        let Some(executable) = self.executables.get_mut(&current.name) else {
+            return;
+        };
+        let mut start = current.since;
+        while start < end {
+            let hour = start / HOUR_MS;
+            let until = end.min((hour + 1) * HOUR_MS);
+            let bucket = executable.hours.entry(hour).or_default();
+            match current.action {
+                Action::Throttle => bucket.throttled_ms += until - start,
+                Action::Boost => bucket.boosted_ms += until - start,
+                Action::Restore => {}
+            }
+            start = until;
+        }
+    }
+
+    /// drop hours beyond [`RETENTION_HOURS`], at most once an hour
+    fn prune(&mut self, now: u64) {
+        let hour = now / HOUR_MS;
+        if hour == self.pruned {
+            return;
+        }
+        self.pruned = hour;
+        let oldest = hour.saturating_sub(RETENTION_HOURS);
+        let active = active(&self.processes);
+        self.executables.retain(|name, executable| {
+            executable.hours = executable.hours.split_off(&oldest);
+            executable.last_seen >= oldest * HOUR_MS || active.contains(name.as_str())
+        });
+    }
+
+    fn report(&self, since: Option<u64>, now: u64) -> Report {
+        let since = since.map(|since| since / HOUR_MS * HOUR_MS);
+        let start = since.unwrap_or_default();
+
+        let active = active(&self.processes);
+        let mut executables = AHashMap::<&str, ExecutableReport>::new();
+        for (name, executable) in &self.executables {
+            if executable.last_seen < start && !active.contains(name.as_str()) {
+                continue;
+            }
+            let mut report = ExecutableReport {
+                name: name.clone(),
+                throttled_ms: 0,
+                boosted_ms: 0,
+                transitions: 0,
+                failures: 0,
+                first_seen: executable.first_seen,
+                last_seen: executable.last_seen,
+            };
+            for bucket in executable
+                .hours
+                .range(start / HOUR_MS..)
+                .map(|(_, bucket)| bucket)
+            {
+                report.throttled_ms += bucket.throttled_ms;
+                report.boosted_ms += bucket.boosted_ms;
+                report.transitions += bucket.transitions;
+                report.failures += bucket.failures;
+            }
+            executables.insert(name, report);
+        }
+
+        // processes still in their state are counted up to now
+        for current in self.processes.values() {
+            let Some(report) = executables.get_mut(current.name.as_str()) else {
+                continue;
+            };
+            let elapsed = now.saturating_sub(current.since.max(start));
+            match current.action {
+                Action::Throttle => report.throttled_ms += elapsed,
+                Action::Boost => report.boosted_ms += elapsed,
+                Action::Restore => {}
+            }
+        }
+
+        let mut executables = executables.into_values().collect::<Vec<_>>();
+        executables.sort_by(|a, b| {
+            (b.throttled_ms, b.boosted_ms)
+                .cmp(&(a.throttled_ms, a.boosted_ms))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Report {
+            since,
+            until: now,
+            executables,
+        }
+    }
+}
+
+/// names of executables with a process in a state set by RustyStar
+fn active(processes: &AHashMap<u32, Current>) -> AHashSet<&str> {
+    processes
+        .values()
+        .map(|current| current.name.as_str())
+        .collect()
+}
+
+impl Executable {
+    fn bucket(&mut self, time: u64) -> &mut Bucket {
+        self.hours.entry(time / HOUR_MS).or_default()
+    }
+}
+
+/// count a decision, called for every [`Record`]
+pub fn observe(record: &Record) {
+    if let Ok(mut stats) = STATS.lock() {
+        stats.observe(record);
+    }
+}
+
+/// the state RustyStar set on `pid` ended, as it exited or was handed back to Windows
+pub fn ended(pid: u32) {
+    if let Ok(mut stats) = STATS.lock() {
+        stats.ended(pid, unix_millis());
+    }
+}
+
+/// statistics of executables seen since `query.since`
+pub fn report(query: &ReportQuery) -> Report {
+    let now = unix_millis();
+    STATS.lock().map_or_else(
+        |_| Report {
+            since: query.since,
+            until: now,
+            executables: vec![],
+        },
+        |stats| stats.report(query.since, now),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HOUR_MS, Stats};
+    use crate::decision::{Action, Outcome, Record, Trigger};
+
+    fn record(time: u64, pid: u32, action: Action, outcome: Outcome) -> Record {
+        Record {
+            time,
+            pid,
+            parent: None,
+            name: "game.exe".to_string(),
+            trigger: Trigger::Background,
+            action: Some(action),
+            reason: "default",
+            outcome,
+            error: None,
+            latency_us: 0,
+        }
+    }
+
+    #[test]
+    fn counts_time_in_state() {
+        let mut stats = Stats::default();
+        let start = 10 * HOUR_MS;
+        stats.observe(&record(start, 1, Action::Throttle, Outcome::Applied));
+        stats.observe(&record(start + 1000, 1, Action::Throttle, Outcome::Applied));
+        stats.observe(&record(start + 3000, 1, Action::Boost, Outcome::Applied));
+        stats.observe(&record(start + 4000, 1, Action::Restore, Outcome::Applied));
+        stats.observe(&record(start + 5000, 2, Action::Throttle, Outcome::Failed));
+        stats.observe(&record(start + 6000, 3, Action::Throttle, Outcome::Paused));
+
+        let report = stats.report(None, start + 10_000);
+        let game = &report.executables[0];
+        assert_eq!(game.throttled_ms, 3000);
+        assert_eq!(game.boosted_ms, 1000);
+        assert_eq!(game.transitions, 3);
+        assert_eq!(game.failures, 1);
+        assert_eq!((game.first_seen, game.last_seen), (start, start + 6000));
+    }
+
+    #[test]
+    fn open_spans_are_clipped_to_report() {
+        let mut stats = Stats::default();
+        let start = 10 * HOUR_MS;
+        stats.observe(&record(start, 1, Action::Throttle, Outcome::Applied));
+        stats.observe(&record(
+            start + 3 * HOUR_MS,
+            2,
+            Action::Boost,
+            Outcome::Skipped,
+        ));
+
+        let report = stats.report(Some(start + 2 * HOUR_MS + 1), start + 3 * HOUR_MS);
+        assert_eq!(report.since, Some(start + 2 * HOUR_MS));
+        assert_eq!(report.executables[0].throttled_ms, HOUR_MS);
+        assert_eq!(report.executables[0].transitions, 0);
+
+        stats.ended(1, start + 3 * HOUR_MS);
+        let report = stats.report(Some(start + HOUR_MS), start + 4 * HOUR_MS);
+        assert_eq!(report.executables[0].throttled_ms, 2 * HOUR_MS);
+    }
+}
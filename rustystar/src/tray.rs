@@ -7,12 +7,19 @@ use std::time::Duration;
 use spdlog::{debug, info};
 use tray_item::{IconSource, TrayItem};
 use windows::Win32::UI::Shell::{SEE_MASK_INVOKEIDLIST, SHELLEXECUTEINFOW, ShellExecuteExW};
-use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
-use windows::core::{PCWSTR, w};
+use windows::Win32::UI::WindowsAndMessaging::{
+    MB_ICONINFORMATION, MB_OK, MessageBoxW, SW_SHOWNORMAL,
+};
+use windows::core::{HSTRING, PCWSTR, w};
 
-use crate::config::Config;
+use crate::cli::format_duration;
+use crate::decision::{Action, Outcome, Query, history, unix_millis};
+use crate::pause::{pause, resume};
 use crate::utils::toggle_all;
 
+/// entries shown by "Recently throttled"
+const RECENTLY_THROTTLED: usize = 15;
+
 fn encode_path(path: &PathBuf) -> Vec<u16> {
     path.as_os_str()
         .encode_wide()
@@ -20,7 +27,10 @@ fn encode_path(path: &PathBuf) -> Vec<u16> {
         .collect::<Vec<u16>>()
 }
 
-pub async fn start_tray_service(log_file: PathBuf) -> Result<(), Box<dyn Error + Send + Sync>> {
+pub async fn start_tray_service(
+    config_file: PathBuf,
+    log_file: PathBuf,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
     let icon = IconSource::Resource("icon0");
     let mut tray = TrayItem::new("RustyStar", icon.clone());
 
@@ -34,7 +44,6 @@ pub async fn start_tray_service(log_file: PathBuf) -> Result<(), Box<dyn Error +
         compio::time::sleep(Duration::from_millis(200)).await;
     };
 
-    let config_file = Config::config_path().await?;
     tray.add_menu_item("Open config", move || unsafe {
         let lpfile = encode_path(&config_file);
         let mut execute_info = SHELLEXECUTEINFOW {
@@ -61,6 +70,17 @@ pub async fn start_tray_service(log_file: PathBuf) -> Result<(), Box<dyn Error +
         };
         _ = ShellExecuteExW((&mut execute_info) as *mut _);
     })?;
+    tray.add_menu_item("Recently throttled", || {
+        // the message box blocks until closed, keep the tray responsive
+        std::thread::spawn(show_recently_throttled);
+    })?;
+    tray.add_menu_item("Pause for 15 minutes", || {
+        pause(Some(Duration::from_secs(15 * 60)));
+    })?;
+    tray.add_menu_item("Pause for 1 hour", || {
+        pause(Some(Duration::from_secs(60 * 60)));
+    })?;
+    tray.add_menu_item("Resume", resume)?;
     tray.add_menu_item("Quit", || {
         info!("received quit signal, recovering...");
         _ = toggle_all(None);
@@ -71,3 +91,40 @@ pub async fn start_tray_service(log_file: PathBuf) -> Result<(), Box<dyn Error +
 
     Ok(())
 }
+
+/// processes throttled last, newest first
+fn show_recently_throttled() {
+    let now = unix_millis();
+    let lines = history(&Query::default())
+        .iter()
+        .rev()
+        .filter(|record| {
+            record.action == Some(Action::Throttle) && record.outcome == Outcome::Applied
+        })
+        .take(RECENTLY_THROTTLED)
+        .map(|record| {
+            let ago = Duration::from_millis(now.saturating_sub(record.time));
+            format!(
+                "{} ago: {} ({}), {}",
+                format_duration(ago),
+                record.name,
+                record.pid,
+                record.trigger
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let message = if lines.is_empty() {
+        "No process was throttled recently.".to_string()
+    } else {
+        lines.join("\n")
+    };
+    unsafe {
+        MessageBoxW(
+            None,
+            &HSTRING::from(message),
+            w!("RustyStar - recently throttled"),
+            MB_OK | MB_ICONINFORMATION,
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+
+use ahash::AHashMap;
+use win32_ecoqos::utils::Process;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::System::Threading::{
+    OpenProcess, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION, QueryFullProcessImageNameW,
+};
+use windows::core::PWSTR;
+
+/// longest path `QueryFullProcessImageNameW` may return, in UTF-16 units
+const MAX_IMAGE_PATH: usize = 32 * 1024;
+
+/// executable names by pid, filled by sweeps and process creation, emptied by exit events
+static IMAGE_NAMES: LazyLock<RwLock<AHashMap<u32, OsString>>> = LazyLock::new(Default::default);
+
+/// executable name of `pid`, only this process is queried if it's unknown
+pub fn image_name(pid: u32) -> Option<OsString> {
+    if let Some(name) = IMAGE_NAMES
+        .read()
+        .ok()
+        .and_then(|names| names.get(&pid).cloned())
+    {
+        return Some(name);
+    }
+
+    let name = query_image_name(pid)?;
+    remember(pid, name.clone());
+    Some(name)
+}
+
+/// whether `pid` runs the executable `name`, case-insensitive
+pub fn image_name_is(pid: u32, name: &str) -> bool {
+    image_name(pid).is_some_and(|image| image.eq_ignore_ascii_case(OsStr::new(name)))
+}
+
+/// `pid` was created running `name`
+pub fn remember(pid: u32, name: OsString) {
+    if let Ok(mut names) = IMAGE_NAMES.write() {
+        names.insert(pid, name);
+    }
+}
+
+/// names of processes enumerated anyway, e.g. by a sweep
+pub fn remember_all<'a>(processes: impl IntoIterator<Item = &'a Process>) {
+    if let Ok(mut names) = IMAGE_NAMES.write() {
+        names.extend(
+            processes
+                .into_iter()
+                .map(|process| (process.process_id, process.process_name.clone())),
+        );
+    }
+}
+
+/// `pid` exited, it may be reused by another process
+pub fn forget(pid: u32) {
+    if let Ok(mut names) = IMAGE_NAMES.write() {
+        names.remove(&pid);
+    }
+}
+
+/// file name of the executable `pid` runs, `None` if it can't be opened
+fn query_image_name(pid: u32) -> Option<OsString> {
+    let hprocess = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+
+    let mut path = vec![0_u16; MAX_IMAGE_PATH];
+    let mut len = path.len() as u32;
+    let queried = unsafe {
+        QueryFullProcessImageNameW(
+            hprocess,
+            PROCESS_NAME_WIN32,
+            PWSTR(path.as_mut_ptr()),
+            &mut len,
+        )
+    };
+    let _ = unsafe { CloseHandle(hprocess) };
+    queried.ok()?;
+
+    let path = OsString::from_wide(&path[..len as usize]);
+    Path::new(&path).file_name().map(OsStr::to_os_string)
+}
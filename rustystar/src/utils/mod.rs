@@ -1,74 +1,130 @@
 use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
+use ahash::AHashSet;
 use spdlog::{debug, warn};
-use win32_ecoqos::process::toggle_efficiency_mode;
 use win32_ecoqos::utils::{Process, Processes};
 use win32_ecoqos::windows_result;
-use windows::Win32::Foundation::{ERROR_ALREADY_EXISTS, GetLastError};
-use windows::Win32::System::Threading::CreateMutexW;
+use windows::Win32::Foundation::{CloseHandle, ERROR_ALREADY_EXISTS, GetLastError};
+use windows::Win32::System::Threading::{CreateMutexW, OpenMutexW, SYNCHRONIZATION_SYNCHRONIZE};
 use windows::core::w;
 
+use crate::CURRENT_FOREGROUND_PID;
 use crate::bypass::whitelisted;
+use crate::decision::{Action, Decision, Facts, Skip, Subject, Trace, Trigger, decide, execute};
+use crate::events::fullscreen_running;
 
+pub mod image_names;
 mod proc_tree;
 pub use proc_tree::ProcTree;
 
-pub fn process_child_process(enable: Option<bool>, main_pid: u32) -> windows_result::Result<()> {
-    let action = match enable {
-        Some(true) => "throtting",
-        Some(false) => "boosting",
-        None => "recovering",
+/// decide for the tree of `main_pid` on `trigger`, the whole tree is skipped if its root is
+///
+/// `received` is when the event was received, for latency of decisions.
+pub fn process_child_process(
+    trigger: Trigger,
+    main_pid: u32,
+    received: Instant,
+) -> windows_result::Result<()> {
+    let fullscreen = trigger == Trigger::Background && fullscreen_running();
+    let facts = |process_name: &OsStr| Facts {
+        whitelisted: whitelisted(process_name),
+        fullscreen,
+        ..Default::default()
     };
 
     let procs = Processes::try_new()?.collect::<Vec<_>>();
-    if let Some(Process { process_name, .. }) = procs
+    image_names::remember_all(&procs);
+    if let Some(root) = procs
         .iter()
         .find(|Process { process_id, .. }| process_id == &main_pid)
     {
-        if whitelisted(process_name) {
-            debug!("[{action:^10}] skipping {process_name:?}");
+        let trace = decide(trigger, &facts(&root.process_name));
+        if let Decision::Skip(_) = trace.decision {
+            _ = execute(Subject::from(root), &trace, received);
             return Ok(());
         }
 
-        debug!("[{action:^10}] process {main_pid:6}: {process_name:?}");
-    } else {
-        debug!("[{action:^10}] process {main_pid:6}");
+        let (decision, process_name) = (trace.decision, &root.process_name);
+        debug!("[{decision:^22}] process tree {main_pid:6}: {process_name:?}");
+    } else if fullscreen {
+        let subject = Subject {
+            pid: main_pid,
+            ..Default::default()
+        };
+        let trace = decide(trigger, &facts(OsStr::new("")));
+        _ = execute(subject, &trace, received);
+        return Ok(());
     }
 
     let relations = ProcTree::from(procs.iter());
 
-    for Process {
-        process_id,
-        process_name,
-        ..
-    } in &procs
-    {
-        if !relations.is_in_tree(main_pid, *process_id) {
-            continue;
-        }
-        if whitelisted(process_name) {
+    for process in &procs {
+        if !relations.is_in_tree(main_pid, process.process_id) {
             continue;
         }
-        if let Err(e) = toggle_efficiency_mode(*process_id, enable) {
-            warn!("failed to toggle {process_name:?}: {e}");
+        let trace = decide(trigger, &facts(&process.process_name));
+        if let Err(e) = execute(Subject::from(process), &trace, received) {
+            warn!("failed to toggle {:?}: {e}", process.process_name);
         }
     }
 
     Ok(())
 }
 
+/// apply `enable` to all processes but whitelisted ones
 pub fn toggle_all(enable: Option<bool>) -> windows_result::Result<()> {
-    for Process {
-        process_id: pid,
-        process_name,
-        ..
-    } in Processes::try_new()?
-    {
-        if whitelisted(&process_name) {
+    let started = Instant::now();
+    let trigger = Trigger::Sweep(Action::from_enable(enable));
+    let procs = Processes::try_new()?.collect::<Vec<_>>();
+    image_names::remember_all(&procs);
+    for process in procs {
+        let facts = Facts {
+            whitelisted: whitelisted(&process.process_name),
+            ..Default::default()
+        };
+        let trace = decide(trigger, &facts);
+        if let Err(e) = execute(Subject::from(&process), &trace, started) {
+            warn!("failed to toggle {:?}: {e}", process.process_name);
+        }
+    }
+
+    Ok(())
+}
+
+/// toggle all processes named in `names`, the foreground tree is never throttled
+pub fn toggle_by_name(
+    names: &AHashSet<OsString>,
+    enable: Option<bool>,
+) -> windows_result::Result<()> {
+    let started = Instant::now();
+    let procs = Processes::try_new()?.collect::<Vec<_>>();
+    let relations = ProcTree::from(procs.iter());
+    let current_fg = CURRENT_FOREGROUND_PID.load(Ordering::Acquire);
+
+    let action = Action::from_enable(enable);
+    for process in &procs {
+        if !names.contains(&process.process_name) {
             continue;
         }
-        if let Err(e) = toggle_efficiency_mode(pid, enable) {
-            warn!("failed to toggle {process_name:?}: {e}");
+        let (rule, decision) = if enable == Some(true)
+            && current_fg != 0
+            && relations.is_in_tree(current_fg, process.process_id)
+        {
+            ("in foreground tree", Decision::Skip(Skip::ForegroundChild))
+        } else {
+            ("whitelist changed", Decision::Apply(action))
+        };
+        let trace = Trace {
+            trigger: Trigger::Sweep(action),
+            steps: vec![],
+            rule: Some(rule),
+            decision,
+        };
+        if let Err(e) = execute(Subject::from(process), &trace, started) {
+            warn!("failed to toggle {:?}: {e}", process.process_name);
         }
     }
 
@@ -86,3 +142,12 @@ pub fn singleton_check() -> Result<bool, Box<dyn Error + Send + Sync>> {
     };
     Ok(true)
 }
+
+/// whether another RustyStar holds the singleton mutex, without taking it
+pub fn instance_running() -> bool {
+    unsafe {
+        OpenMutexW(SYNCHRONIZATION_SYNCHRONIZE, false, w!("RustyStar"))
+            .inspect(|handle| _ = CloseHandle(*handle))
+            .is_ok()
+    }
+}
@@ -7,7 +7,7 @@ use windows::Win32::System::Threading::{
     SetProcessInformation, IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS, PROCESS_CREATION_FLAGS,
     PROCESS_INFORMATION_CLASS, PROCESS_POWER_THROTTLING_CURRENT_VERSION,
     PROCESS_POWER_THROTTLING_EXECUTION_SPEED, PROCESS_POWER_THROTTLING_STATE,
-    PROCESS_SET_INFORMATION,
+    PROCESS_QUERY_INFORMATION, PROCESS_SET_INFORMATION,
 };
 
 unsafe fn toggle_efficiency_mode_impl(
@@ -112,12 +112,48 @@ pub unsafe fn toggle_efficiency_mode_handle(
     }
 }
 
+/// power throttling state of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlingState {
+    /// EcoQoS is in effect.
+    pub enabled: bool,
+    /// The state was set explicitly, e.g. by [`toggle_efficiency_mode`],
+    /// instead of being decided by the system.
+    pub explicit: bool,
+}
+
 /// check whether EcoQoS is enabled on a process.
 ///
 /// `hprocess` must have `PROCESS_QUERY_INFORMATION` access right.
 ///
 /// SAFETY: `hprocess` must be a valid process handle. DO NOT pass null ptr, e.g.
 pub unsafe fn ecoqos_enabled(hprocess: HANDLE) -> Result<bool, windows_result::Error> {
+    unsafe { throttling_state_handle(hprocess) }.map(|state| state.enabled)
+}
+
+/// Query power throttling state of a process, by it's PID.
+///
+/// ```rust
+/// use win32_ecoqos::process::throttling_state;
+///
+/// let state = throttling_state(std::process::id());
+/// ```
+pub fn throttling_state(pid: u32) -> Result<ThrottlingState, windows_result::Error> {
+    let hprocess = unsafe { OpenProcess(PROCESS_QUERY_INFORMATION, false, pid)? };
+    let result = unsafe { throttling_state_handle(hprocess) };
+    let _ = unsafe { CloseHandle(hprocess) };
+
+    result
+}
+
+/// Query power throttling state of a process, by a [`HANDLE`](https://microsoft.github.io/windows-docs-rs/doc/windows/Win32/Foundation/struct.HANDLE.html).
+///
+/// `hprocess` must have `PROCESS_QUERY_INFORMATION` access right.
+///
+/// SAFETY: `hprocess` must be a valid process handle. DO NOT pass null ptr, e.g.
+pub unsafe fn throttling_state_handle(
+    hprocess: HANDLE,
+) -> Result<ThrottlingState, windows_result::Error> {
     let mut process_info = PROCESS_POWER_THROTTLING_STATE {
         Version: PROCESS_POWER_THROTTLING_CURRENT_VERSION,
         ControlMask: 0,
@@ -133,8 +169,9 @@ pub unsafe fn ecoqos_enabled(hprocess: HANDLE) -> Result<bool, windows_result::E
         )?;
     }
 
-    Ok(
-        process_info.StateMask & PROCESS_POWER_THROTTLING_EXECUTION_SPEED
-            == PROCESS_POWER_THROTTLING_EXECUTION_SPEED,
-    )
+    let speed = PROCESS_POWER_THROTTLING_EXECUTION_SPEED;
+    Ok(ThrottlingState {
+        enabled: process_info.StateMask & speed == speed,
+        explicit: process_info.ControlMask & speed == speed,
+    })
 }
@@ -1,4 +1,6 @@
-use win32_ecoqos::process::{ecoqos_enabled, toggle_efficiency_mode_handle};
+use win32_ecoqos::process::{
+    ecoqos_enabled, throttling_state_handle, toggle_efficiency_mode_handle, ThrottlingState,
+};
 use windows::Win32::System::Threading::GetCurrentProcess;
 
 #[test]
@@ -14,3 +16,23 @@ pub fn test_process_ecoqos() -> Result<(), windows_result::Error> {
 
     Ok(())
 }
+
+#[test]
+pub fn test_process_throttling_state() -> Result<(), windows_result::Error> {
+    unsafe {
+        let hprocess = GetCurrentProcess();
+
+        toggle_efficiency_mode_handle(hprocess, Some(true))?;
+        assert_eq!(
+            throttling_state_handle(hprocess)?,
+            ThrottlingState {
+                enabled: true,
+                explicit: true
+            }
+        );
+        toggle_efficiency_mode_handle(hprocess, None)?;
+        assert!(!throttling_state_handle(hprocess)?.explicit);
+    }
+
+    Ok(())
+}